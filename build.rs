@@ -0,0 +1,20 @@
+use std::process::Command;
+
+/// Minimum version of SDL_image, for animations and detection of recent formats
+const MIN_SDL_IMAGE_VERSION: (u32, u32) = (2, 6);
+
+/// Check the version of SDL_image, when it can be found with pkg-config
+fn main() {
+    println!("cargo::rerun-if-env-changed=PKG_CONFIG_PATH");
+    let Ok(output) = Command::new("pkg-config").args(["--modversion", "SDL2_image"]).output() else { return };
+    if !output.status.success() {
+        return;  // not installed with pkg-config, let the linker check
+    }
+    let version = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    let mut numbers = version.split('.').map(|n| n.parse::<u32>().unwrap_or(0));
+    let (major, minor) = (numbers.next().unwrap_or(0), numbers.next().unwrap_or(0));
+    if (major, minor) < MIN_SDL_IMAGE_VERSION {
+        let (min_major, min_minor) = MIN_SDL_IMAGE_VERSION;
+        println!("cargo::error=SDL_image {min_major}.{min_minor} or later is required, found {version}");
+    }
+}
@@ -1,26 +1,73 @@
-use std::fs;
 use std::cell::Cell;
+use std::fs;
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 use sdl2::mouse::{MouseButton, MouseState, MouseWheelDirection};
 use sdl2::event::{Event, WindowEvent};
 use sdl2::keyboard::{Keycode, Mod, Scancode};
 use sdl2::pixels::Color;
 use sdl2::rect::Rect;
-use std::sync::LazyLock;
+use crate::bindings::{Action, Bindings, Input, Modifiers};
+use crate::clipboard::ImageCopier;
+use crate::config::Config;
+use crate::diff::{self, DiffStats};
 use crate::display::{Display, Image, Font};
-
+use crate::exif;
+use crate::filelist;
+use crate::format::Formats;
+use crate::gallery::Gallery;
+use crate::histogram::{self, Histogram};
+use crate::inspector::PixelInfo;
+use crate::prefetch::Prefetcher;
+use crate::save;
+use crate::sort::SortOrder;
+use crate::thumbnail::Thumbnailer;
+use crate::view::View;
+use crate::watch::Watcher;
+
+
+/// Size and `RGBA32` pixels of an image, or an error
+type PixelsResult = Result<(u32, u32, Vec<u8>), String>;
 
 /// The main application
 pub struct App {
     display: Display,
+    config: Config,
+    /// Actions bound to keys and mouse buttons
+    bindings: Bindings,
+    /// Supported image formats
+    formats: Formats,
     /// Requested paths to display
     paths: Vec<PathBuf>,
     /// Files to display (image paths only)
     files: Vec<PathBuf>,
+    /// Order of `files`
+    sort_order: SortOrder,
     /// Index of current file in `files`
     file_index: Option<usize>,
     /// Current image (None if `file_index` is None)
     image: Option<CurrentImage>,
+    /// Decode files around the current one in the background
+    prefetcher: Prefetcher,
+    /// Watch requested paths for changes, None if disabled or not available
+    watcher: Option<Watcher>,
+    /// Time to reload the current file and number of failed attempts, if it changed on disk
+    reload: Option<(Instant, u32)>,
+    /// Image compared with the current one, None if not comparing
+    compare: Option<Compare>,
+    /// Display the alpha channel of images, as greyscale
+    alpha_view: bool,
+    /// Display the histogram of the current image
+    show_histogram: bool,
+    /// Short message, displayed until the given time
+    toast: Option<(String, Instant)>,
+    /// Thumbnail grid, None in the single image view
+    gallery: Option<Gallery>,
+    /// Create gallery thumbnails in the background
+    thumbnailer: Thumbnailer,
+    /// Copy images to the clipboard in the background
+    image_copier: ImageCopier,
     /// Current zoom level
     zoom: f32,
     /// True if a redraw is required
@@ -31,50 +78,226 @@ pub struct App {
 struct CurrentImage {
     /// Current image
     image: Image,
+    /// Alpha channel of the image, created when displayed
+    alpha: Option<Image>,
+    /// Histogram of the displayed frame, with the frame index, computed when displayed
+    histogram: Option<(usize, Histogram)>,
     /// Pixel displayed at the center of the screen
     pos: (f32, f32),
     /// Rotation angle, in degrees
     angle: i32,
+    /// Horizontal and vertical flips, applied before the rotation
+    flip: (bool, bool),
     /// Last drag position, None if drag is not active
     drag: Option<(i32, i32)>,
     /// Displayed pixel information
-    pixel_info: Option<((i32, i32), Color)>,
+    pixel_info: Option<PixelInfo>,
+    /// Selected rectangle
+    selection: Option<Selection>,
+    /// Time of the next animation frame, None if not animated or paused
+    next_frame: Option<Instant>,
+}
+
+/// Rectangle selected on an image
+struct Selection {
+    /// Corner where the drag started, in image coordinates
+    start: (f32, f32),
+    /// Opposite corner, it follows the mouse while dragging
+    end: (f32, f32),
+    /// True while the selection is dragged
+    dragging: bool,
+}
+
+/// Layout of compared images
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum CompareLayout {
+    /// Images next to each other, each in half of the window
+    SideBySide,
+    /// Images on top of each other, split by a draggable vertical line
+    Split,
+    /// Difference of the images, pixel by pixel
+    Difference,
+}
+
+/// Image compared with the current one, displayed with the same view
+struct Compare {
+    /// Compared file, from `files`
+    path: PathBuf,
+    /// Compared image, None if it could not be loaded
+    image: Option<Image>,
+    /// Alpha channel of the compared image, created when displayed
+    alpha: Option<Image>,
+    layout: CompareLayout,
+    /// Position of the split line, relative to the window width
+    split: f32,
+    /// True while the split line is dragged
+    split_drag: bool,
+    /// Difference image and statistics, computed when displayed, or the reason it cannot be
+    diff: Option<Result<(Image, DiffStats), String>>,
+    /// Factor applied to differences
+    amplification: f32,
+    /// Differences lower than or equal to this value are masked
+    threshold: u8,
+}
+
+/// Image displayed in a pane
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum PaneContent {
+    Current,
+    Compared,
+    Difference,
+}
+
+/// Part of the window displaying an image
+struct Pane {
+    viewport: Rect,
+    /// View of the image, in the viewport
+    view: View,
+    content: PaneContent,
 }
 
-/// Zoom steps used when zooming in/out
-static ZOOM_STEPS: LazyLock<Vec<f32>> = LazyLock::new(|| {
-    (0..0)
-        .chain((  15..  50).step_by(   7))
-        .chain((  50.. 100).step_by(  10))
-        .chain(( 100.. 200).step_by(  25))
-        .chain(( 200.. 600).step_by( 100))
-        .chain(( 600..1000).step_by( 200))
-        .chain((1000..2000).step_by( 500))
-        .chain((2000..5000).step_by(1000))
-        .map(|v| v as f32 / 100.).collect()
-});
+impl CurrentImage {
+    /// Return the image view, for given zoom and output size
+    fn view(&self, zoom: f32, output_size: (u32, u32)) -> View {
+        View {
+            image_size: size_as!(self.image.size(), f32),
+            output_size: size_as!(output_size, f32),
+            pos: self.pos,
+            zoom,
+            angle: self.angle,
+            flip: self.flip,
+        }
+    }
+}
+
+impl Compare {
+    /// Return the horizontal position of the split line, for given window width
+    fn split_x(&self, width: u32) -> u32 {
+        (width as f32 * self.split).round() as u32
+    }
+}
+
+impl Selection {
+    /// Return the selected pixels: position and size, None if empty
+    fn rect(&self) -> Option<(u32, u32, u32, u32)> {
+        let (x0, x1) = (self.start.0.min(self.end.0).round(), self.start.0.max(self.end.0).round());
+        let (y0, y1) = (self.start.1.min(self.end.1).round(), self.start.1.max(self.end.1).round());
+        let (width, height) = (x1 - x0, y1 - y0);
+        (width >= 1. && height >= 1.).then_some((x0 as u32, y0 as u32, width as u32, height as u32))
+    }
+}
+
+impl Pane {
+    /// Convert screen position to image position, None if outside the image
+    fn screen_to_image(&self, pos: (f32, f32)) -> Option<(f32, f32)> {
+        Some(self.screen_to_image_clamped(pos)).filter(|p| self.view.contains(*p))
+    }
+
+    /// Convert screen position to image position, clamp it to the image
+    fn screen_to_image_clamped(&self, pos: (f32, f32)) -> (f32, f32) {
+        let pos = (pos.0 - self.viewport.x() as f32, pos.1 - self.viewport.y() as f32);
+        let (x, y) = self.view.screen_to_image(pos);
+        let (sx, sy) = self.view.image_size;
+        (x.clamp(0., sx), y.clamp(0., sy))
+    }
+}
 
 
 impl App {
-    const DEFAULT_WINDOW_SIZE: (u32, u32) = (800, 500);
-    const DEFAULT_BG_COLOR: Color = Color { r: 0, g: 0, b: 0, a: 255 };
-    const FILE_INFO_COLOR: Color = Color { r: 0, g: 255, b: 0, a: 255 };
     const FILE_INFO_POS: (i32, i32) = (10, 5);
-    const PIXEL_INFO_COLOR: Color = Color { r: 255, g: 0, b: 255, a: 255 };
     const PIXEL_INFO_POS: (i32, i32) = (10, 30);
     const OUTLINE_COLOR: Color = Color { r: 0, g: 0, b: 0, a: 255 };
+    const ALPHA_INFO_COLOR: Color = Color { r: 200, g: 200, b: 200, a: 255 };
+    /// Delay before reloading a changed file, to let writes settle
+    const RELOAD_DELAY: Duration = Duration::from_millis(200);
+    /// Delay between attempts to load a file being written
+    const RELOAD_RETRY_DELAY: Duration = Duration::from_millis(500);
+    const RELOAD_ATTEMPTS: u32 = 10;
+    /// Files modified more recently may still be being written
+    const RECENT_WRITE: Duration = Duration::from_secs(5);
+    /// Gallery scroll of a mouse wheel step, in pixels
+    const GALLERY_WHEEL_STEP: i32 = 50;
+    const GALLERY_FAILED_COLOR: Color = Color { r: 64, g: 64, b: 64, a: 255 };
+    const COMPARE_DIVIDER_COLOR: Color = Color { r: 128, g: 128, b: 128, a: 255 };
+    const DIFF_INFO_POS: (i32, i32) = (10, 55);
+    const INFO_LINE_HEIGHT: i32 = 25;
+    /// Background of overlay panels
+    const PANEL_COLOR: Color = Color { r: 0, g: 0, b: 0, a: 160 };
+    /// Size of a magnified pixel in the loupe
+    const LOUPE_CELL_SIZE: u32 = 8;
+    const TOAST_DURATION: Duration = Duration::from_secs(2);
+    const TOAST_PADDING: u32 = 6;
+    const TOAST_BOTTOM_MARGIN: i32 = 40;
+    const ERROR_COLOR: Color = Color { r: 255, g: 64, b: 64, a: 255 };
+    const MAX_AMPLIFICATION: f32 = 256.;
+    const HISTOGRAM_SIZE: (u32, u32) = (320, 272);
+    const HISTOGRAM_PADDING: i32 = 8;
+    const HISTOGRAM_GRAPH_HEIGHT: u32 = 40;
+    /// Height of a channel: graph and text
+    const HISTOGRAM_ROW_HEIGHT: i32 = 64;
+    const HISTOGRAM_INDICATOR_WIDTH: u32 = 4;
+    /// Colors of red, green, blue and luminance
+    const HISTOGRAM_COLORS: [Color; 4] = [
+        Color { r: 255, g: 80, b: 80, a: 255 },
+        Color { r: 80, g: 255, b: 80, a: 255 },
+        Color { r: 80, g: 140, b: 255, a: 255 },
+        Color { r: 220, g: 220, b: 220, a: 255 },
+    ];
+    const CLIPPING_COLOR: Color = Color { r: 255, g: 255, b: 0, a: 255 };
+    const NO_CLIPPING_COLOR: Color = Color { r: 64, g: 64, b: 64, a: 255 };
+    /// Maximum distance to grab the split line, in pixels
+    const SPLIT_GRAB_DISTANCE: i32 = 8;
 
     /// Create the application, initialize files from paths
-    pub fn init(paths: Vec<PathBuf>) -> Result<Self, String> {
-        let mut display = Display::init(Self::DEFAULT_WINDOW_SIZE)?;
-        display.bg_color = Self::DEFAULT_BG_COLOR;
-
+    pub fn init(paths: Vec<PathBuf>, config: Config) -> Result<Self, String> {
+        let mut display = Display::init(config.window_size, (config.font_size, config.mono_font_size))?;
+        display.bg_color = config.colors.background;
+        display.scale_filter = config.scale_filter;
+        display.checkerboard = config.checkerboard.then(|| config.checkerboard());
+
+        let prefetcher = Prefetcher::new(config.prefetch_count, config.cache_size.saturating_mul(1 << 20));
+        let bindings = Bindings::new(&config.bindings);
+        let sort_order = SortOrder::new(config.sort, config.sort_reverse);
+        let formats = Formats::init(&config);
+        let watcher = if config.watch {
+            let event_subsystem = display.sdl_context.event()?;
+            let event_type = unsafe { event_subsystem.register_event()? };
+            Watcher::new(&paths, config.recursive, event_subsystem.event_sender(), event_type)
+                .inspect_err(|e| eprintln!("cannot watch files: {e}"))
+                .ok()
+        } else {
+            None
+        };
+        let thumbnailer = {
+            let event_subsystem = display.sdl_context.event()?;
+            let event_type = unsafe { event_subsystem.register_event()? };
+            Thumbnailer::new(config.thumbnail_size, event_subsystem.event_sender(), event_type)
+        };
+        let image_copier = {
+            let event_subsystem = display.sdl_context.event()?;
+            let event_type = unsafe { event_subsystem.register_event()? };
+            ImageCopier::new(event_subsystem.event_sender(), event_type)
+        };
         let mut app = Self {
             display,
+            config,
+            bindings,
+            formats,
             paths,
             files: Vec::new(),
+            sort_order,
             file_index: None,
             image: None,
+            prefetcher,
+            watcher,
+            reload: None,
+            compare: None,
+            alpha_view: false,
+            show_histogram: false,
+            toast: None,
+            gallery: None,
+            thumbnailer,
+            image_copier,
             zoom: 1.,
             dirty: Cell::new(true),
         };
@@ -90,9 +313,18 @@ impl App {
         //TODO disable unneeded events
         let mut pump = self.display.sdl_context.event_pump()?;
         loop {
-            let event = pump.wait_event();
+            let event = match self.event_timeout() {
+                Some(timeout) => pump.wait_event_timeout(timeout),
+                None => Some(pump.wait_event()),
+            };
+            self.animate();
+            self.reload_if_due();
+            self.expire_toast();
+            let Some(event) = event else {
+                self.refresh();
+                continue;
+            };
             match event {
-                // quit event, or Escape
                 Event::Quit{..} => { return Ok(()) },
                 Event::Window{ win_event, .. } => {
                     match win_event {
@@ -114,6 +346,15 @@ impl App {
                 Event::MouseMotion{ mousestate, x, y, .. } => {
                     self.handle_mouse_move(mousestate, (x, y), &pump);
                 },
+                Event::User{ type_, .. } if self.watcher.as_ref().is_some_and(|w| w.event_type == type_) => {
+                    self.handle_file_changes();
+                },
+                Event::User{ type_, .. } if type_ == self.thumbnailer.event_type => {
+                    self.handle_thumbnails();
+                },
+                Event::User{ type_, .. } if type_ == self.image_copier.event_type => {
+                    self.handle_copied_images();
+                },
                 Event::MouseWheel{ x, y, direction, .. } => {
                     let (dx, dy) = match direction {
                         MouseWheelDirection::Flipped => (-x, -y),
@@ -134,33 +375,23 @@ impl App {
     ///
     /// Missing paths are ignored.
     pub fn update_filelist(&mut self, next_file: Option<PathBuf>) -> Result<(), String> {
-        let mut files = Vec::<PathBuf>::new();
-        for path in &self.paths {
-            if path.as_os_str().is_empty() || path.is_dir() {
-                for entry in fs::read_dir(path).map_err(|e| e.to_string())? {
-                    let entry_path = entry.map_err(|e| e.to_string())?.path();
-                    if is_image_path(&entry_path) {
-                        files.push(entry_path);
-                    }
-                }
-            } else if path.is_file() {
-                let owned_path = path.clone();
-                if is_image_path(&owned_path) {
-                    files.push(owned_path);
-                }
-            }
-        }
-
+        let selected = self.gallery_selected_path();
+        let mut files = filelist::list_files(&self.paths, &self.config, &self.formats)?;
         files.sort_unstable();
         files.dedup();
+        self.sort_order.sort(&mut files);
 
         self.files = files;
+        // files may have changed on disk
+        self.prefetcher.clear();
 
         let start_index = next_file
             .filter(|p| p.is_file())
             .and_then(|p| self.files.iter().position(|f| p.as_path() == f))
             .unwrap_or(0);
         self.change_file(Some(start_index));
+        self.prefetch();
+        self.update_gallery_selection(selected);
 
         self.zoom_adjust();
 
@@ -181,29 +412,392 @@ impl App {
         }
 
         self.file_index = new_index;
+        self.load_current_file();
+    }
 
+    /// (Re)load the current file
+    fn load_current_file(&mut self) {
+        self.reload = None;
         self.image = {
             let index = try_some!(self.file_index);
-            match self.display.load_image(&self.files[index]) {
-                Ok(image) => {
+            let path = &self.files[index];
+            let result = self.prefetcher.get(path).and_then(|decoded| {
+                let image = self.display.create_image(path, decoded.clone())?;
+                Ok((image, decoded.orientation))
+            });
+            match result {
+                Ok((image, orientation)) => {
                     let (sx, sy) = size_as!(image.size(), f32);
+                    let (angle, flip_h) = match orientation {
+                        Some(value) if self.config.exif_rotate => exif::orientation_transform(value),
+                        _ => (0, false),
+                    };
                     Some(CurrentImage {
                         image,
+                        alpha: None,
+                        histogram: None,
                         pos: (sx / 2., sy / 2.),  // centered
-                        angle: 0,
+                        angle,
+                        flip: (flip_h, false),
                         drag: None,
                         pixel_info: None,
+                        selection: None,
+                        next_frame: None,
                     })
                 }
                 Err(e) => {
-                    eprintln!("failed to load image: {e}");
+                    if is_recently_modified(path) {
+                        // probably being written, try again later
+                        self.prefetcher.invalidate(path);
+                        self.reload = Some((Instant::now() + Self::RELOAD_RETRY_DELAY, 1));
+                    } else {
+                        eprintln!("failed to load image: {e}");
+                    }
                     None
                 }
             }
         };
+        self.invalidate_diff();
+        self.prefetch();
+        self.play_animation(true);
+        self.dirty.set(true);
+    }
+
+    /// Update the file list from paths changed on disk
+    ///
+    /// The current file is kept, unless it has been removed or a new file is followed.
+    fn handle_file_changes(&mut self) {
+        let changed = try_some!(self.watcher.as_ref()).changed_paths();
+        let current = self.file_index.map(|i| self.files[i].clone());
+        let selected = self.gallery_selected_path();
+        for path in &changed {
+            self.prefetcher.invalidate(path);
+            if let Some(gallery) = self.gallery.as_mut() {
+                gallery.thumbnails.remove(path);
+            }
+        }
+        if let Some(current) = &current && changed.contains(current) && current.is_file() {
+            // reload once writes are done
+            self.reload = Some((Instant::now() + Self::RELOAD_DELAY, 0));
+        }
+        if self.compare.as_ref().is_some_and(|c| changed.contains(&c.path)) {
+            self.load_compared();
+        }
+        let mut added = Vec::new();
+        let mut removed = false;
+        for path in changed {
+            if !path.exists() {
+                // a removed directory removes its files
+                let len = self.files.len();
+                self.files.retain(|f| !f.starts_with(&path));
+                removed |= self.files.len() != len;
+            } else if path.is_dir() {
+                // directory moved into a watched one
+                if self.config.recursive && filelist::is_in_scope(&path, &self.paths, &self.config) {
+                    let files = filelist::list_files(std::slice::from_ref(&path), &self.config, &self.formats).unwrap_or_default();
+                    added.extend(files.into_iter().filter(|f| filelist::is_in_scope(f, &self.paths, &self.config) && !self.files.contains(f)));
+                }
+            } else if !self.files.contains(&path) && filelist::is_listed(&path, &self.paths, &self.config, &self.formats) {
+                added.push(path);
+            }
+        }
+        if added.is_empty() && !removed {
+            self.dirty.set(true);  // thumbnails may have changed
+            return;
+        }
+
+        self.files.extend(added.iter().cloned());
+        self.sort_order.sort(&mut self.files);
+
+        let newest = added.iter()
+            .max_by_key(|p| fs::metadata(p).and_then(|m| m.modified()).ok())
+            .filter(|_| self.config.follow);
+        let next_file = newest.or(current.as_ref());
+        match next_file.and_then(|p| self.files.iter().position(|f| f == p)) {
+            Some(index) if next_file == current.as_ref() => {
+                // the current image is unchanged, don't reload it
+                self.file_index = Some(index);
+                self.prefetch();
+            },
+            index => {
+                // current file removed: display the file that replaced it in the list
+                let index = index.or(self.file_index).map_or(0, |i| i.min(self.files.len().saturating_sub(1)));
+                // force a reload, even if the index is unchanged
+                self.file_index = None;
+                self.image = None;
+                self.change_file(Some(index));
+                self.zoom_adjust();
+            },
+        }
+        self.update_gallery_selection(selected);
+        self.dirty.set(true);
+    }
+
+    /// Change the order of the file list, keep the current file
+    pub fn set_sort_order(&mut self, order: SortOrder) {
+        self.sort_order = order;
+        let current = self.file_index.map(|i| self.files[i].clone());
+        let selected = self.gallery_selected_path();
+        self.sort_order.sort(&mut self.files);
+        // the current image is unchanged, don't reload it
+        self.file_index = current.and_then(|path| self.files.iter().position(|f| *f == path));
+        self.prefetch();
+        self.update_gallery_selection(selected);
+        self.dirty.set(true);
+    }
+
+    /// Start comparing the current image with the next file, or stop comparing
+    pub fn toggle_compare(&mut self) {
+        let adjusted = self.is_adjusted();
+        if self.compare.take().is_none() {
+            let index = try_some!(self.file_index);
+            if self.files.len() < 2 {
+                return;
+            }
+            self.compare = Some(Compare {
+                path: self.files[(index + 1) % self.files.len()].clone(),
+                image: None,
+                alpha: None,
+                layout: CompareLayout::SideBySide,
+                split: 0.5,
+                split_drag: false,
+                diff: None,
+                amplification: 1.,
+                threshold: 0,
+            });
+            self.load_compared();
+        }
+        self.keep_adjusted(adjusted);
+    }
+
+    /// Switch between side by side, split and difference comparison
+    pub fn cycle_compare_layout(&mut self) {
+        let adjusted = self.is_adjusted();
+        let compare = try_some!(self.compare.as_mut());
+        compare.layout = match compare.layout {
+            CompareLayout::SideBySide => CompareLayout::Split,
+            CompareLayout::Split => CompareLayout::Difference,
+            CompareLayout::Difference => CompareLayout::SideBySide,
+        };
+        compare.diff = None;
+        self.keep_adjusted(adjusted);
+    }
+
+    /// Change how differences are displayed, in difference comparison
+    pub fn set_diff_params(&mut self, amplification: f32, threshold: u8) {
+        let compare = try_some!(self.compare.as_mut());
+        if compare.layout != CompareLayout::Difference {
+            return;
+        }
+        compare.amplification = amplification.clamp(1., Self::MAX_AMPLIFICATION);
+        compare.threshold = threshold;
+        self.invalidate_diff();
+    }
+
+    /// Compute the difference between the current and compared images, if displayed and not done yet
+    fn update_diff(&mut self) {
+        let index = try_some!(self.file_index);
+        let path = &self.files[index];
+        let compare = try_some!(self.compare.as_mut());
+        if compare.layout != CompareLayout::Difference || compare.diff.is_some() {
+            return;
+        }
+        let result = self.prefetcher.get(path)
+            .and_then(|current| Ok((current, self.prefetcher.get(&compare.path)?)))
+            .and_then(|(current, compared)| diff::difference(&current, &compared, compare.amplification, compare.threshold))
+            .and_then(|(decoded, stats)| Ok((self.display.create_image(path, Arc::new(decoded))?, stats)));
+        compare.diff = Some(result);
+    }
+
+    /// Drop the difference image, to compute it again
+    fn invalidate_diff(&mut self) {
+        if let Some(compare) = self.compare.as_mut() {
+            compare.diff = None;
+        }
+        self.dirty.set(true);
+    }
+
+    /// (Re)load the compared file
+    fn load_compared(&mut self) {
+        let compare = try_some!(self.compare.as_mut());
+        compare.image = self.prefetcher.get(&compare.path)
+            .and_then(|decoded| self.display.create_image(&compare.path, decoded))
+            .inspect_err(|e| eprintln!("failed to load image: {e}"))
+            .ok();
+        compare.alpha = None;
+        self.invalidate_diff();
+    }
+
+    /// Change the compared file, relative, skip the current file
+    pub fn change_compared_rel(&mut self, offset: i32) {
+        let compare = try_some!(self.compare.as_mut());
+        let count = self.files.len() as i32;
+        if count == 0 {
+            return;
+        }
+        let index = self.files.iter().position(|f| *f == compare.path).unwrap_or(0) as i32;
+        let mut index = (index + offset).rem_euclid(count);
+        if self.file_index == Some(index as usize) {
+            index = (index + offset.signum()).rem_euclid(count);
+        }
+        compare.path = self.files[index as usize].clone();
+        self.load_compared();
+    }
+
+    /// Swap the current and compared images, keep the view
+    pub fn swap_compared(&mut self) {
+        let output_size = self.image_output_size();
+        let compare = try_some!(self.compare.as_mut());
+        let compared = try_some!(compare.image.as_mut());
+        let current = try_some!(self.image.as_mut());
+        let current_index = try_some!(self.file_index);
+        let index = try_some!(self.files.iter().position(|f| *f == compare.path));
+
+        let view = current.view(self.zoom, output_size).with_image_size(size_as!(compared.size(), f32));
+        std::mem::swap(&mut current.image, compared);
+        std::mem::swap(&mut current.alpha, &mut compare.alpha);
+        current.histogram = None;
+        current.pos = view.pos;
+        current.pixel_info = None;
+        current.selection = None;
+        compare.path = self.files[current_index].clone();
+        self.file_index = Some(index);
+        self.reload = None;
+        self.invalidate_diff();
+        self.prefetch();
+        self.play_animation(true);
+        self.clamp_pos();
+    }
+
+    /// Adjust zoom if the whole image was displayed, clamp position otherwise
+    fn keep_adjusted(&mut self, adjusted: bool) {
+        if adjusted {
+            self.zoom_adjust();
+        } else {
+            self.clamp_pos();
+        }
         self.dirty.set(true);
     }
 
+    /// Return the size of the part of the window displaying the current image
+    fn image_output_size(&self) -> (u32, u32) {
+        let (width, height) = self.display.size();
+        match &self.compare {
+            Some(compare) if compare.layout == CompareLayout::SideBySide => (width / 2, height),
+            _ => (width, height),
+        }
+    }
+
+    /// Return the parts of the window displaying the current and the compared images
+    fn panes(&self) -> Vec<Pane> {
+        let Some(image) = self.image.as_ref() else { return Vec::new() };
+        let (width, height) = self.display.size();
+        let view = image.view(self.zoom, self.image_output_size());
+        let Some(compare) = &self.compare else {
+            return vec![Pane { viewport: Rect::new(0, 0, width, height), view, content: PaneContent::Current }];
+        };
+        let compared_view = compare.image.as_ref().map(|i| view.with_image_size(size_as!(i.size(), f32)));
+
+        let mut panes = Vec::new();
+        match compare.layout {
+            CompareLayout::SideBySide => {
+                let half = width / 2;
+                panes.push(Pane { viewport: Rect::new(0, 0, half, height), view, content: PaneContent::Current });
+                if let Some(view) = compared_view {
+                    panes.push(Pane { viewport: Rect::new(half as i32, 0, half, height), view, content: PaneContent::Compared });
+                }
+            },
+            CompareLayout::Split => {
+                // both images are placed as if displayed alone, each one is only drawn on its side
+                let split = compare.split_x(width);
+                if split > 0 {
+                    let rect = (0., 0., split as f32, height as f32);
+                    panes.push(Pane { viewport: Rect::new(0, 0, split, height), view: view.sub_view(rect), content: PaneContent::Current });
+                }
+                if let Some(view) = compared_view && split < width {
+                    let rect = (split as f32, 0., (width - split) as f32, height as f32);
+                    panes.push(Pane { viewport: Rect::new(split as i32, 0, width - split, height), view: view.sub_view(rect), content: PaneContent::Compared });
+                }
+            },
+            CompareLayout::Difference => {
+                // the difference has the size of the current image
+                if let Some(Ok(_)) = &compare.diff {
+                    panes.push(Pane { viewport: Rect::new(0, 0, width, height), view, content: PaneContent::Difference });
+                }
+            },
+        }
+        panes
+    }
+
+    /// Return the pane at a screen position
+    fn pane_at(&self, pos: (f32, f32)) -> Option<Pane> {
+        let pos = size_as!(pos, i32);
+        self.panes().into_iter().find(|p| p.viewport.contains_point(pos))
+    }
+
+    /// Switch between the single image view and the gallery
+    ///
+    /// The animation is paused while the gallery is displayed.
+    pub fn toggle_gallery(&mut self) {
+        if let Some(gallery) = self.gallery.take() {
+            self.thumbnailer.request(&[]);
+            self.play_animation(!gallery.animation_paused);
+        } else {
+            let mut gallery = Gallery::new(self.file_index.unwrap_or(0), self.config.thumbnail_size.pixels());
+            gallery.scroll_to_selected(self.display.size());
+            gallery.animation_paused = self.image.as_ref().is_some_and(|image| image.next_frame.is_none());
+            self.gallery = Some(gallery);
+            self.play_animation(false);
+        }
+        self.dirty.set(true);
+    }
+
+    /// Leave the gallery, display the selected file
+    pub fn open_selected(&mut self) {
+        let selected = try_some!(self.gallery.as_ref()).selected;
+        self.toggle_gallery();
+        self.change_file(Some(selected));
+        self.zoom_adjust();
+    }
+
+    /// Return the path of the file selected in the gallery
+    fn gallery_selected_path(&self) -> Option<PathBuf> {
+        let gallery = self.gallery.as_ref()?;
+        self.files.get(gallery.selected).cloned()
+    }
+
+    /// Keep the gallery selection on the same file, after the file list changed
+    fn update_gallery_selection(&mut self, selected: Option<PathBuf>) {
+        let output_size = self.display.size();
+        let gallery = try_some!(self.gallery.as_mut());
+        gallery.selected = selected
+            .and_then(|path| self.files.iter().position(|f| *f == path))
+            .unwrap_or(gallery.selected)
+            .min(self.files.len().saturating_sub(1));
+        gallery.scroll_to_selected(output_size);
+    }
+
+    /// Create textures of the thumbnails created in the background
+    fn handle_thumbnails(&mut self) {
+        let results = self.thumbnailer.results();
+        let gallery = try_some!(self.gallery.as_mut());
+        for (path, result) in results {
+            let image = result.and_then(|decoded| self.display.create_image(&path, Arc::new(decoded)));
+            if let Err(e) = &image {
+                eprintln!("cannot create thumbnail: {}: {e}", path.display());
+            }
+            gallery.thumbnails.insert(path, image.ok());
+        }
+        gallery.evict(&self.files, self.display.size());
+        self.dirty.set(true);
+    }
+
+    /// Preload files around the current one
+    fn prefetch(&mut self) {
+        let index = try_some!(self.file_index);
+        self.prefetcher.update(&self.files, index);
+    }
+
     /// Change current file, relative
     pub fn change_file_rel(&mut self, offset: i32) {
         let nfiles = self.files.len() as i32;
@@ -218,20 +812,21 @@ impl App {
         self.change_file(index)
     }
 
-    /// Move image to absolute position
+    /// Move image to absolute position, in oriented image coordinates
     pub fn move_to(&mut self, pos: (f32, f32)) {
-        let image = try_some!(self.image.as_mut());
-        image.pos = pos;
+        let view = try_some!(self.view());
+        let image = self.image.as_mut().unwrap();
+        image.pos = view.unorient(pos);
         self.clamp_pos();
         self.dirty.set(true);
     }
 
-    /// Move image, relatively to current position
+    /// Move image, relatively to current position, along screen axes
     pub fn move_rel(&mut self, offset: (f32, f32)) {
         let pos = {
-            let image = try_some!(self.image.as_ref());
+            let view = try_some!(self.view());
             let (dx, dy) = offset;
-            let (px, py) = image.pos;
+            let (px, py) = view.orient(view.pos);
             (px + dx, py + dy)
         };
         self.move_to(pos);
@@ -239,10 +834,10 @@ impl App {
 
     /// Scroll pages, preserve zoom (step is 1 for one screen height)
     pub fn scroll(&mut self, step: f32) {
-        let image = try_some!(self.image.as_mut());
-        let (_, out_sy) = size_as!(self.display.size(), f32);
-        let (_, img_sy) = size_as!(image.image.size(), f32);
-        let (_, pos_y) = image.pos;
+        let view = try_some!(self.view());
+        let (_, out_sy) = view.output_size;
+        let (_, img_sy) = view.oriented_size();
+        let (_, pos_y) = view.orient(view.pos);
 
         let dy = step * out_sy / self.zoom;
         // small margin to avoid avoid blocking near the bottom
@@ -260,48 +855,30 @@ impl App {
 
     /// Clamp image position if needed
     fn clamp_pos(&mut self) {
-        let image = try_some!(self.image.as_mut());
-        let (out_sx, out_sy) = size_as!(self.display.size(), f32);
-        let (img_sx, img_sy) = size_as!(image.image.size(), f32);
-        let (dst_sx, dst_sy) = (out_sx / self.zoom, out_sy / self.zoom);
-
-        let (px, py) = image.pos;
-        // center or clamp
-        let px = if img_sx <= dst_sx {
-            img_sx / 2.
-        } else {
-            px.clamp(dst_sx / 2., img_sx - dst_sx / 2.)
-        };
-        let py = if img_sy <= dst_sy {
-            img_sy / 2.
-        } else {
-            py.clamp(dst_sy / 2., img_sy - dst_sy / 2.)
-        };
-
-        image.pos = (px, py);
+        let view = try_some!(self.view());
+        let image = self.image.as_mut().unwrap();
+        image.pos = view.clamped_pos();
         self.dirty.set(true);
     }
 
     /// Adjust zoom level to display the whole image
     pub fn zoom_adjust(&mut self) {
-        let image = try_some!(self.image.as_ref());
-        let (out_sx, out_sy) = size_as!(self.display.size(), f32);
-        let (img_sx, img_sy) = size_as!(image.image.size(), f32);
-        self.zoom = 1f32.min(out_sx / img_sx).min(out_sy / img_sy);
+        let view = try_some!(self.view());
+        self.zoom = view.fit_zoom();
         self.clamp_pos();
         self.dirty.set(true);
     }
 
     /// Zoom in, by one step
     pub fn zoom_in(&mut self, center: Option<(f32, f32)>) {
-        if let Some(zoom) = ZOOM_STEPS.iter().find(|z| **z > self.zoom) {
+        if let Some(zoom) = self.config.zoom_steps.iter().find(|z| **z > self.zoom) {
             self.set_zoom(*zoom, center);
         }
     }
 
     /// Zoom out, by one step
     pub fn zoom_out(&mut self, center: Option<(f32, f32)>) {
-        if let Some(zoom) = ZOOM_STEPS.iter().rev().find(|z| **z < self.zoom) {
+        if let Some(zoom) = self.config.zoom_steps.iter().rev().find(|z| **z < self.zoom) {
             self.set_zoom(*zoom, center);
         }
     }
@@ -334,82 +911,653 @@ impl App {
 
     /// Return true if the whole image fits in the display
     fn is_adjusted(&self) -> bool {
-        let image = try_some!(self.image.as_ref(), true);
-
-        let (out_sx, out_sy) = size_as!(self.display.size(), f32);
-        let (img_sx, img_sy) = size_as!(image.image.size(), f32);
-
-        // Round because of possible accuracy issues for large images
-        out_sx >= (img_sx * self.zoom).round() && out_sy >= (img_sy * self.zoom).round()
+        let view = try_some!(self.view(), true);
+        view.is_adjusted()
     }
 
-    /// Rotate image to given angle, in degrees
+    /// Rotate image to given angle, in degrees (multiple of 90)
     pub fn rotate_to(&mut self, angle: i32) {
+        let adjusted = self.is_adjusted();
         let image = try_some!(self.image.as_mut());
-        image.angle = angle % 360;
+        image.angle = angle.rem_euclid(360);
+        // keep the whole image visible if it was
+        if adjusted {
+            self.zoom_adjust();
+        } else {
+            self.clamp_pos();
+        }
         self.dirty.set(true);
     }
 
-    /// Rotate image by given angle, in degrees
+    /// Rotate image by given angle, in degrees (multiple of 90)
     pub fn rotate_rel(&mut self, angle: i32) {
+        let current = try_some!(self.image.as_ref()).angle;
+        self.rotate_to(current + angle);
+    }
+
+    /// Display a short message, replace the previous one
+    fn show_toast(&mut self, text: String) {
+        self.toast = Some((text, Instant::now() + Self::TOAST_DURATION));
+        self.dirty.set(true);
+    }
+
+    /// Hide the message if it is time to
+    fn expire_toast(&mut self) {
+        if self.toast.as_ref().is_some_and(|(_, time)| Instant::now() >= *time) {
+            self.toast = None;
+            self.dirty.set(true);
+        }
+    }
+
+    /// Copy text to the clipboard, confirm with a message
+    fn copy_text(&mut self, name: &str, text: String) {
+        match self.display.set_clipboard_text(&text) {
+            Ok(()) => self.show_toast(format!("copied {name}: {text}")),
+            Err(e) => {
+                eprintln!("cannot copy {name}: {e}");
+                self.show_toast(format!("cannot copy {name}"));
+            },
+        }
+    }
+
+    /// Copy information of the picked pixel to the clipboard, display a message if there is none
+    fn copy_picked(&mut self, name: &str, text: impl Fn(&PixelInfo, &Config) -> String) {
+        let image = try_some!(self.image.as_ref());
+        match &image.pixel_info {
+            Some(info) => {
+                let text = text(info, &self.config);
+                self.copy_text(name, text);
+            },
+            None => self.show_toast("no picked pixel, move the mouse while holding Ctrl".to_string()),
+        }
+    }
+
+    /// Copy the absolute path of the current file to the clipboard
+    fn copy_path(&mut self) {
+        let image = try_some!(self.image.as_ref());
+        let path = std::path::absolute(&image.image.path).unwrap_or_else(|_| PathBuf::from(&image.image.path));
+        self.copy_text("path", path.to_string_lossy().into_owned());
+    }
+
+    /// Return the selected rectangle of the current image, None if there is none
+    fn selection_rect(&self) -> Option<(u32, u32, u32, u32)> {
+        self.image.as_ref()?.selection.as_ref()?.rect()
+    }
+
+    /// Return the selected rectangle in the current orientation, as displayed and exported
+    fn oriented_selection_rect(&self) -> Option<(u32, u32, u32, u32)> {
+        let (x, y, width, height) = self.selection_rect()?;
+        let view = self.view()?;
+        let (x0, y0) = view.orient(size_as!((x, y), f32));
+        let (x1, y1) = view.orient(size_as!((x + width, y + height), f32));
+        let (x, y) = (x0.min(x1).round(), y0.min(y1).round());
+        let (width, height) = ((x1 - x0).abs().round(), (y1 - y0).abs().round());
+        Some((x as u32, y as u32, width as u32, height as u32))
+    }
+
+    /// Return the pixels of the selection, or of the whole image, with the current orientation
+    ///
+    /// Pixels are taken from the displayed frame.
+    fn selected_pixels(&mut self) -> Option<PixelsResult> {
+        let view = self.view()?;
+        let index = self.file_index?;
+        let current = self.image.as_ref()?;
+        let rect = self.selection_rect().unwrap_or((0, 0, current.image.width, current.image.height));
+        let frame = current.image.frame();
+        let result = self.prefetcher.get(&self.files[index]).map(|decoded| {
+            let cropped = decoded.crop(frame, rect);
+            save::orient_pixels(&cropped, &view.with_image_size(size_as!(cropped.size(), f32)))
+        });
+        Some(result)
+    }
+
+    /// Copy the selection, or the current image, to the clipboard, with its orientation
+    ///
+    /// The copy is done in the background, a message is displayed once done.
+    fn copy_image(&mut self) {
+        let name = if self.selection_rect().is_some() { "selection" } else { "image" };
+        match try_some!(self.selected_pixels()) {
+            Ok((width, height, pixels)) => self.image_copier.copy(name, width, height, pixels),
+            Err(e) => {
+                eprintln!("cannot copy {name}: {e}");
+                self.show_toast(format!("cannot copy {name}"));
+            },
+        }
+    }
+
+    /// Report images copied to the clipboard
+    fn handle_copied_images(&mut self) {
+        for (name, result) in self.image_copier.results() {
+            match result {
+                Ok((width, height)) => self.show_toast(format!("copied {name}: {width} × {height}")),
+                Err(e) => {
+                    eprintln!("cannot copy {name}: {e}");
+                    self.show_toast(format!("cannot copy {name}: {e}"));
+                },
+            }
+        }
+    }
+
+    /// Save the selection to a new PNG file, next to the current file, with its orientation
+    fn save_selection(&mut self) {
+        let path = try_some!(self.image.as_ref()).image.path.clone();
+        let Some(rect) = self.oriented_selection_rect() else {
+            self.show_toast("no selection, drag the mouse while holding Shift".to_string());
+            return;
+        };
+        let path = save::crop_path(Path::new(&path), rect);
+        match try_some!(self.selected_pixels()).and_then(|(width, height, pixels)| save::save_png(&path, width, height, &pixels)) {
+            Ok(()) => self.show_toast(format!("saved selection: {}", path.display())),
+            Err(e) => {
+                eprintln!("cannot save selection: {e}");
+                self.show_toast("cannot save selection".to_string());
+            },
+        }
+    }
+
+    /// Remove the selection of the current image
+    fn clear_selection(&mut self) {
+        try_some!(self.image.as_mut()).selection = None;
+        self.dirty.set(true);
+    }
+
+    /// Save the image with its current orientation, after confirmation
+    pub fn save_oriented(&mut self) {
+        let image = try_some!(self.image.as_ref());
+        let path = PathBuf::from(&image.image.path);
+        let description = match save::save_description(&path, self.config.exif_rotate) {
+            Ok(description) => description,
+            Err(e) => {
+                self.show_toast(format!("cannot save image: {e}"));
+                return;
+            },
+        };
+        let message = format!(
+            "Overwrite {} with the rotated image?\n{description}\nThe original file will be kept as {}",
+            image.image.path, save::backup_path(&path).display());
+        if !self.display.confirm("Save image", &message) {
+            return;
+        }
+
+        if let Err(e) = save::save_oriented(&path, image.angle, image.flip, self.config.exif_rotate) {
+            eprintln!("failed to save image: {e}");
+            self.show_toast(format!("cannot save image: {e}"));
+            return;
+        }
+        // reload the image, its position in the file list is unchanged
+        let adjusted = self.is_adjusted();
+        self.prefetcher.invalidate(&path);
+        self.load_current_file();
+        if adjusted {
+            self.zoom_adjust();
+        } else {
+            self.clamp_pos();
+        }
+    }
+
+    /// Flip image horizontally or vertically, along screen axes
+    pub fn flip(&mut self, horizontal: bool) {
+        let transposed = try_some!(self.view()).is_transposed();
+        let image = self.image.as_mut().unwrap();
+        // flips are applied before rotation: swap axes if rotated by 90 degrees
+        if horizontal != transposed {
+            image.flip.0 = !image.flip.0;
+        } else {
+            image.flip.1 = !image.flip.1;
+        }
+        self.clamp_pos();
+        self.dirty.set(true);
+    }
+
+    /// Play or pause the animation
+    pub fn play_animation(&mut self, play: bool) {
+        let image = try_some!(self.image.as_mut());
+        image.next_frame =
+            if play && image.image.is_animated() {
+                Some(Instant::now() + Duration::from_millis(image.image.frame_delay().into()))
+            } else {
+                None
+            };
+        self.dirty.set(true);
+    }
+
+    /// Toggle animation pause
+    pub fn toggle_animation(&mut self) {
+        let playing = try_some!(self.image.as_ref()).next_frame.is_some();
+        self.play_animation(!playing);
+    }
+
+    /// Pause the animation and step frames
+    pub fn step_frame(&mut self, offset: i32) {
+        self.play_animation(false);
+        let image = try_some!(self.image.as_mut());
+        image.image.step_frame(offset);
+        self.dirty.set(true);
+    }
+
+    /// Display the next animation frame if it is time to
+    fn animate(&mut self) {
         let image = try_some!(self.image.as_mut());
-        image.angle = (image.angle + angle) % 360;
+        let next_frame = try_some!(image.next_frame);
+        let now = Instant::now();
+        if now < next_frame {
+            return;
+        }
+        image.image.step_frame(1);
+        let delay = Duration::from_millis(image.image.frame_delay().into());
+        // don't try to catch up if late (e.g. when the window is moved)
+        image.next_frame = Some((next_frame + delay).max(now));
+        self.dirty.set(true);
+    }
+
+    /// Reload the current file if it changed on disk and it is time to
+    ///
+    /// View is preserved if image size is unchanged.
+    /// If the file cannot be decoded (e.g. being written), the current image is kept and loading is retried.
+    fn reload_if_due(&mut self) {
+        let (time, attempts) = try_some!(self.reload);
+        if Instant::now() < time {
+            return;
+        }
+        self.reload = None;
+        let index = try_some!(self.file_index);
+        let path = self.files[index].clone();
+
+        let decoded = match self.prefetcher.get(&path) {
+            Ok(decoded) => decoded,
+            Err(e) => {
+                self.prefetcher.invalidate(&path);
+                if attempts < Self::RELOAD_ATTEMPTS {
+                    self.reload = Some((Instant::now() + Self::RELOAD_RETRY_DELAY, attempts + 1));
+                } else {
+                    eprintln!("failed to reload image: {e}");
+                }
+                self.dirty.set(true);
+                return;
+            },
+        };
+
+        let same_size = self.image.as_ref()
+            .is_some_and(|i| i.image.size() == decoded.size());
+        if same_size {
+            match self.display.create_image(&path, decoded.clone()) {
+                Ok(image) => {
+                    let current = self.image.as_mut().unwrap();
+                    current.image = image;
+                    current.alpha = None;
+                    current.histogram = None;
+                    current.pixel_info = None;
+                    self.invalidate_diff();
+                    self.play_animation(true);
+                },
+                Err(e) => eprintln!("failed to reload image: {e}"),
+            }
+        } else {
+            self.load_current_file();
+            self.zoom_adjust();
+        }
         self.dirty.set(true);
     }
 
+    /// Return the time to wait for the next animation frame or reload, in milliseconds
+    fn event_timeout(&self) -> Option<u32> {
+        let next_frame = self.image.as_ref().and_then(|i| i.next_frame);
+        let reload = self.reload.map(|(time, _)| time);
+        let toast = self.toast.as_ref().map(|(_, time)| *time);
+        let deadline = [next_frame, reload, toast].into_iter().flatten().min()?;
+        let timeout = deadline.saturating_duration_since(Instant::now()).as_millis();
+        Some(timeout.clamp(1, u32::MAX as u128) as u32)
+    }
+
     /// Redraw the screen, forcily
     pub fn redraw(&mut self) {
         self.display.clear();
 
         //TODO don't redraw the text each time, keep it in a texture
         let file_text =
-            if let Some(file_index) = self.file_index {
-                if let Some(image) = self.image.as_ref() {
-                    self.display.draw_image(&image.image, image.pos, self.zoom, image.angle);
-                    format!(
-                        "{}  ( {} × {} )  [ {} / {} ]  {} %",
-                        image.image.path,
+            if self.gallery.is_some() {
+                self.draw_gallery()
+            } else if let Some(file_index) = self.file_index {
+                if self.image.is_some() {
+                    self.draw_panes();
+                    let image = self.image.as_ref().unwrap();
+                    let mut text = format!(
+                        "{}  ( {} × {} )  [ {} / {} ]  {} %  scale: {}  sort: {}{}",
+                        filelist::display_path(&self.files[file_index], &self.paths, &self.config),
                         image.image.width,
                         image.image.height,
                         file_index + 1, self.files.len(),
                         (self.zoom * 100.) as u32,
-                    )
+                        self.display.scale_filter.name(),
+                        self.sort_order.key.name(),
+                        if self.sort_order.reverse { " (reversed)" } else { "" },
+                    );
+                    if image.image.is_animated() {
+                        text += &format!(
+                            "  frame {} / {}{}",
+                            image.image.frame() + 1, image.image.frame_count(),
+                            if image.next_frame.is_none() { " (paused)" } else { "" },
+                        );
+                    }
+                    if self.alpha_view {
+                        text += "  [alpha]";
+                    }
+                    if let Some(compare) = &self.compare {
+                        let path = filelist::display_path(&compare.path, &self.paths, &self.config);
+                        text += &match &compare.image {
+                            Some(image) => format!("  compare: {path}  ( {} × {} )", image.width, image.height),
+                            None => format!("  compare: {path}  [invalid file]"),
+                        };
+                    }
+                    text
                 } else {
                     format!(
-                        "[invalid file]  [ {} / {} ]",
+                        "{}  [ {} / {} ]",
+                        if self.reload.is_some() { "[loading]" } else { "[invalid file]" },
                         file_index + 1, self.files.len(),
                     )
                 }
             } else {
                 "[no file]".to_string()
             };
-        self.display.draw_text_outline(Font::Normal, file_text.as_str(), Self::FILE_INFO_COLOR, Self::OUTLINE_COLOR, Self::FILE_INFO_POS);
+        self.display.draw_text_outline(Font::Normal, file_text.as_str(), self.config.colors.file_info, Self::OUTLINE_COLOR, Self::FILE_INFO_POS);
+
+        let mut info_y = Self::DIFF_INFO_POS.1;
+        let diff = self.compare.as_ref().and_then(|c| Some((c.diff.as_ref()?, c))).filter(|_| self.gallery.is_none());
+        if let Some((diff, compare)) = diff {
+            let (text, color) = match diff {
+                Ok((_, stats)) => (format_diff_stats(stats, compare.amplification, compare.threshold), self.config.colors.pixel_info),
+                Err(e) => (format!("cannot compute the difference: {e}"), Self::ERROR_COLOR),
+            };
+            self.display.draw_text_outline(Font::Normal, &text, color, Self::OUTLINE_COLOR, Self::DIFF_INFO_POS);
+            info_y += Self::INFO_LINE_HEIGHT;
+        }
 
-        if let Some((pixel_pos, color)) = self.image.as_ref().and_then(|i| i.pixel_info).as_ref() {
-            let mut pos = Self::PIXEL_INFO_POS;
-            pos = self.display.draw_text_outline(
-                Font::Normal, format!("( {} , {} )  ", pixel_pos.0, pixel_pos.1).as_str(),
-                Self::PIXEL_INFO_COLOR, Self::OUTLINE_COLOR, pos);
-            self.display.draw_rectangle(Rect::new(pos.0, pos.1, 15, 15), *color);
-            pos.0 += 15;
-            pos = self.display.draw_text_outline(
-                Font::Normal, format!("  #{:02X}{:02X}{:02X}  ", color.r, color.g, color.b).as_str(),
-                Self::PIXEL_INFO_COLOR, Self::OUTLINE_COLOR, pos);
-            pos = self.display.draw_text_outline(
-                Font::Normal, format!(" {}", color.r).as_str(),
-                Color::RGB(255, 0, 0), Self::OUTLINE_COLOR, pos);
-            pos = self.display.draw_text_outline(
-                Font::Normal, format!(" {}", color.g).as_str(),
-                Color::RGB(0, 255, 0), Self::OUTLINE_COLOR, pos);
-            /*pos =*/ self.display.draw_text_outline(
-                Font::Normal, format!(" {}", color.b).as_str(),
-                Color::RGB(0, 0, 255), Self::OUTLINE_COLOR, pos);
+        let selection = self.oriented_selection_rect().filter(|_| self.gallery.is_none());
+        if let Some((x, y, width, height)) = selection {
+            let text = format!("selection ( {x} , {y} )  {width} × {height}  ratio {}", format_ratio(width, height));
+            self.display.draw_text_outline(Font::Normal, &text, self.config.colors.selection, Self::OUTLINE_COLOR, (Self::DIFF_INFO_POS.0, info_y));
+            info_y += Self::INFO_LINE_HEIGHT;
+        }
+
+        if self.show_histogram && self.gallery.is_none() {
+            self.update_histogram();
+            self.draw_histogram();
+        }
+
+        if self.gallery.is_none() {
+            self.draw_pixel_info(info_y);
+        }
+
+        if let Some((text, _)) = &self.toast {
+            let (width, height) = self.display.size();
+            let (text_sx, text_sy) = self.display.text_size(Font::Normal, text);
+            let padding = Self::TOAST_PADDING;
+            let rect = Rect::new(
+                (width as i32 - text_sx as i32) / 2 - padding as i32, height as i32 - text_sy as i32 - 2 * padding as i32 - Self::TOAST_BOTTOM_MARGIN,
+                text_sx + 2 * padding, text_sy + 2 * padding);
+            self.display.draw_rectangle(rect, Self::PANEL_COLOR);
+            self.display.draw_text_outline(Font::Normal, text, Color::WHITE, Self::OUTLINE_COLOR, (rect.x() + padding as i32, rect.y() + padding as i32));
         }
 
         self.display.refresh();
         self.dirty.set(false);
     }
 
+    /// Return the image displayed in a pane, its alpha channel in alpha view
+    fn pane_image<'a>(content: PaneContent, image: &'a mut Option<CurrentImage>, compare: &'a mut Option<Compare>, alpha_view: bool) -> Option<&'a mut Image> {
+        let (image, alpha) = match content {
+            PaneContent::Current => {
+                let current = image.as_mut()?;
+                (&mut current.image, &mut current.alpha)
+            },
+            PaneContent::Compared => {
+                let compare = compare.as_mut()?;
+                (compare.image.as_mut()?, &mut compare.alpha)
+            },
+            PaneContent::Difference => return compare.as_mut()?.diff.as_mut()?.as_mut().ok().map(|(image, _)| image),
+        };
+        match alpha.as_mut().filter(|_| alpha_view) {
+            Some(alpha) => {
+                alpha.set_frame(image.frame());
+                Some(alpha)
+            },
+            None => Some(image),
+        }
+    }
+
+    /// Create the alpha channels of the displayed images, in alpha view
+    fn update_alpha_images(&mut self) {
+        if !self.alpha_view {
+            return;
+        }
+        let mut create = |path: &Path| self.prefetcher.get(path)
+            .and_then(|decoded| self.display.create_image(path, Arc::new(decoded.alpha_channel())))
+            .inspect_err(|e| eprintln!("failed to create alpha channel: {e}"))
+            .ok();
+        if let Some(index) = self.file_index && let Some(current) = self.image.as_mut() && current.alpha.is_none() {
+            current.alpha = create(&self.files[index]);
+        }
+        if let Some(compare) = self.compare.as_mut() && compare.image.is_some() && compare.alpha.is_none() {
+            compare.alpha = create(&compare.path);
+        }
+    }
+
+    /// Toggle the alpha view, drop alpha channels when disabled
+    pub fn toggle_alpha_view(&mut self) {
+        self.alpha_view = !self.alpha_view;
+        if !self.alpha_view {
+            if let Some(current) = self.image.as_mut() {
+                current.alpha = None;
+            }
+            if let Some(compare) = self.compare.as_mut() {
+                compare.alpha = None;
+            }
+        }
+        self.dirty.set(true);
+    }
+
+    /// Draw the current image, and the compared one
+    fn draw_panes(&mut self) {
+        self.update_diff();
+        self.update_alpha_images();
+        for pane in self.panes() {
+            let Some(image) = Self::pane_image(pane.content, &mut self.image, &mut self.compare, self.alpha_view) else { continue };
+            self.display.draw_image(image, &pane.view, pane.viewport);
+        }
+        self.draw_selection();
+
+        let compare = try_some!(self.compare.as_ref());
+        let (width, height) = self.display.size();
+        let x = match compare.layout {
+            CompareLayout::SideBySide => width / 2,
+            CompareLayout::Split => compare.split_x(width),
+            CompareLayout::Difference => return,
+        };
+        self.display.draw_rectangle(Rect::new(x as i32 - 1, 0, 2, height), Self::COMPARE_DIVIDER_COLOR);
+    }
+
+    /// Draw the outline of the selection, on each pane
+    fn draw_selection(&mut self) {
+        let (x, y, width, height) = try_some!(self.selection_rect());
+        let (cx, cy) = size_as!(try_some!(self.image.as_ref()).image.size(), f32);
+        for pane in self.panes() {
+            // compared images may have a different size
+            let (sx, sy) = pane.view.image_size;
+            let region = (x as f32 * sx / cx, y as f32 * sy / cy, width as f32 * sx / cx, height as f32 * sy / cy);
+            let (rx, ry, rw, rh) = pane.view.region_draw_rect(region);
+            // the draw rectangle is rotated around its center
+            let (rx, ry, rw, rh) = if pane.view.is_transposed() {
+                (rx + (rw - rh) / 2., ry + (rh - rw) / 2., rh, rw)
+            } else {
+                (rx, ry, rw, rh)
+            };
+            let rect = Rect::new(
+                pane.viewport.x() + rx.round() as i32, pane.viewport.y() + ry.round() as i32,
+                rw.round().max(1.) as u32, rh.round().max(1.) as u32);
+            let outer = Rect::new(rect.x() - 1, rect.y() - 1, rect.width() + 2, rect.height() + 2);
+            self.display.draw_outline(outer, 1, Self::OUTLINE_COLOR);
+            self.display.draw_outline(rect, 1, self.config.colors.selection);
+        }
+    }
+
+    /// Draw the information of the picked pixel
+    ///
+    /// Color values are drawn on a single line, additional fields from `fields_y`.
+    /// The loupe is drawn in the bottom left corner.
+    fn draw_pixel_info(&mut self, mut fields_y: i32) {
+        let info = try_some!(self.image.as_ref().and_then(|i| i.pixel_info.as_ref()));
+        let fields = &self.config.inspector;
+        let color = info.pixel.color;
+        let has_alpha = info.shows_alpha(fields);
+        let mut pos = Self::PIXEL_INFO_POS;
+        pos = self.display.draw_text_outline(
+            Font::Normal, format!("( {} , {} )  ", info.pos.0, info.pos.1).as_str(),
+            self.config.colors.pixel_info, Self::OUTLINE_COLOR, pos);
+        self.display.draw_rectangle(Rect::new(pos.0, pos.1, 15, 15), Color::RGB(color.r, color.g, color.b));
+        pos.0 += 15;
+        pos = self.display.draw_text_outline(
+            Font::Normal, format!("  {}  ", info.hex(fields)).as_str(),
+            self.config.colors.pixel_info, Self::OUTLINE_COLOR, pos);
+        pos = self.display.draw_text_outline(
+            Font::Normal, format!(" {}", color.r).as_str(),
+            Color::RGB(255, 0, 0), Self::OUTLINE_COLOR, pos);
+        pos = self.display.draw_text_outline(
+            Font::Normal, format!(" {}", color.g).as_str(),
+            Color::RGB(0, 255, 0), Self::OUTLINE_COLOR, pos);
+        pos = self.display.draw_text_outline(
+            Font::Normal, format!(" {}", color.b).as_str(),
+            Color::RGB(0, 0, 255), Self::OUTLINE_COLOR, pos);
+        if has_alpha {
+            pos = self.display.draw_text_outline(
+                Font::Normal, format!(" {}", color.a).as_str(),
+                Self::ALPHA_INFO_COLOR, Self::OUTLINE_COLOR, pos);
+        }
+        if info.pixel.has_custom_depth() {
+            // values in the source format, e.g. 5-6-5 bits
+            let channels = info.pixel.channel_bits.iter().zip(info.pixel.source_values()).filter(|(bits, _)| **bits > 0);
+            let (bits, values): (Vec<_>, Vec<_>) = channels.map(|(bits, value)| (bits.to_string(), value.to_string())).unzip();
+            self.display.draw_text_outline(
+                Font::Normal, format!("    {} bits: {}", bits.join("-"), values.join(" ")).as_str(),
+                self.config.colors.pixel_info, Self::OUTLINE_COLOR, pos);
+        }
+
+        for text in fields.iter().filter_map(|field| info.field_text(*field)) {
+            self.display.draw_text_outline(
+                Font::Normal, &text, self.config.colors.pixel_info, Self::OUTLINE_COLOR, (Self::PIXEL_INFO_POS.0, fields_y));
+            fields_y += Self::INFO_LINE_HEIGHT;
+        }
+
+        if info.loupe_size == 0 {
+            return;
+        }
+        let cell = Self::LOUPE_CELL_SIZE;
+        let size = info.loupe_size * cell;
+        let height = self.display.size().1;
+        let panel = Rect::new(10, height as i32 - size as i32 - 10, size, size);
+        self.display.draw_rectangle(panel, Self::PANEL_COLOR);
+        for (i, color) in info.loupe.iter().enumerate() {
+            let Some(color) = color else { continue };
+            let (col, row) = (i as u32 % info.loupe_size, i as u32 / info.loupe_size);
+            self.display.draw_rectangle(Rect::new(panel.x() + (col * cell) as i32, panel.y() + (row * cell) as i32, cell, cell), *color);
+        }
+        // outline the picked pixel, visible on any color
+        let center = (info.loupe_size / 2 * cell) as i32;
+        self.display.draw_outline(Rect::new(panel.x() + center - 2, panel.y() + center - 2, cell + 4, cell + 4), 1, Self::OUTLINE_COLOR);
+        self.display.draw_outline(Rect::new(panel.x() + center - 1, panel.y() + center - 1, cell + 2, cell + 2), 1, Color::WHITE);
+    }
+
+    /// Compute the histogram of the displayed frame, if not done yet
+    fn update_histogram(&mut self) {
+        let index = try_some!(self.file_index);
+        let image = try_some!(self.image.as_mut());
+        let frame = image.image.frame();
+        if image.histogram.as_ref().is_some_and(|(f, _)| *f == frame) {
+            return;
+        }
+        match self.prefetcher.get(&self.files[index]) {
+            Ok(decoded) => image.histogram = Some((frame, Histogram::new(&decoded.frames[frame].pixels))),
+            Err(e) => eprintln!("cannot compute histogram: {e}"),
+        }
+    }
+
+    /// Draw the histogram of the current image, in the bottom right corner
+    ///
+    /// Indicators on both sides of graphs are lit when pixels are clipped to pure black or white.
+    fn draw_histogram(&mut self) {
+        let image = try_some!(self.image.as_ref());
+        let (_, histogram) = try_some!(image.histogram.as_ref());
+        let (width, height) = self.display.size();
+        let (panel_sx, panel_sy) = Self::HISTOGRAM_SIZE;
+        let panel = Rect::new(width as i32 - panel_sx as i32 - 10, height as i32 - panel_sy as i32 - 10, panel_sx, panel_sy);
+        self.display.draw_rectangle(panel, Self::PANEL_COLOR);
+
+        let graph_height = Self::HISTOGRAM_GRAPH_HEIGHT;
+        let indicator_width = Self::HISTOGRAM_INDICATOR_WIDTH as i32;
+        for (channel, color) in Self::HISTOGRAM_COLORS.into_iter().enumerate() {
+            let top = panel.y() + Self::HISTOGRAM_PADDING + channel as i32 * Self::HISTOGRAM_ROW_HEIGHT;
+            let left = panel.x() + Self::HISTOGRAM_PADDING + indicator_width + 2;
+            let values = &histogram.channels[channel];
+            // clipped values are not used for the scale, they would flatten the graph
+            let max = values[1..255].iter().copied().max().unwrap_or(0).max(1) as u64;
+            let bars: Vec<Rect> = values.iter().enumerate()
+                .filter(|(_, n)| **n > 0)
+                .map(|(value, n)| {
+                    let bar_height = (*n as u64 * graph_height as u64).div_ceil(max).min(graph_height as u64) as u32;
+                    Rect::new(left + value as i32, top + (graph_height - bar_height) as i32, 1, bar_height)
+                })
+                .collect();
+            self.display.draw_rectangles(&bars, color);
+
+            let (black, white) = histogram.clipping(channel);
+            for (ratio, x) in [(black, left - indicator_width - 2), (white, left + 256 + 2)] {
+                let indicator_color = if ratio > 0. { Self::CLIPPING_COLOR } else { Self::NO_CLIPPING_COLOR };
+                self.display.draw_rectangle(Rect::new(x, top, Self::HISTOGRAM_INDICATOR_WIDTH, graph_height), indicator_color);
+            }
+            let text = format!(
+                "{} mean {:.1} median {} clip {:.2}% / {:.2}%",
+                histogram::CHANNEL_NAMES[channel], histogram.mean(channel), histogram.median(channel),
+                black * 100., white * 100.,
+            );
+            self.display.draw_text_outline(Font::Mono, &text, color, Self::OUTLINE_COLOR, (left - indicator_width - 2, top + graph_height as i32 + 2));
+        }
+    }
+
+    /// Draw the visible thumbnails, request missing ones, return the file information
+    fn draw_gallery(&mut self) -> String {
+        let output_size = self.display.size();
+        let gallery = self.gallery.as_mut().unwrap();
+        if self.files.is_empty() {
+            return "[no file]".to_string();
+        }
+
+        let visible = gallery.visible_range(output_size, self.files.len());
+        let mut missing = Vec::new();
+        for index in visible.clone() {
+            let path = &self.files[index];
+            let rect = gallery.thumbnail_rect(index, output_size);
+            match gallery.thumbnails.get_mut(path) {
+                Some(Some(image)) => self.display.draw_image_fit(image, rect),
+                Some(None) => self.display.draw_outline(rect, 1, Self::GALLERY_FAILED_COLOR),
+                None => missing.push(path.clone()),
+            }
+        }
+        self.display.draw_outline(gallery.cell_rect(gallery.selected, output_size), 2, self.config.colors.selection);
+
+        // also prepare the next page, visible thumbnails first
+        let ahead = visible.end..(visible.end + visible.len()).min(self.files.len());
+        missing.extend(self.files[ahead].iter().filter(|p| !gallery.thumbnails.contains_key(*p)).cloned());
+        self.thumbnailer.request(&missing);
+
+        format!(
+            "{}  [ {} / {} ]  sort: {}{}",
+            filelist::display_path(&self.files[gallery.selected], &self.paths, &self.config),
+            gallery.selected + 1, self.files.len(),
+            self.sort_order.key.name(),
+            if self.sort_order.reverse { " (reversed)" } else { "" },
+        )
+    }
+
     /// Redraw the screen if dirty
     pub fn refresh(&mut self) {
         if self.dirty.get() {
@@ -427,78 +1575,163 @@ impl App {
 
     /// Handle text input events
     fn handle_textinput(&mut self, text: &str) {
-        match text {
-            // zoom
-            "a" => self.zoom_adjust(),
-            "z" => self.set_zoom(1., None),
-            "-" => self.zoom_out(None),
-            "+" => self.zoom_in(None),
-            // rotation
-            "r" => self.rotate_rel(90),
-            "R" => self.rotate_rel(-90),
-
-            "q" => self.quit(),
-            "f" => self.display.toggle_fullscreen(),
-
-            _ => {},
+        let mut chars = text.chars();
+        let (Some(c), None) = (chars.next(), chars.next()) else { return };
+        let mods = self.display.sdl_context.keyboard().mod_state().into();
+        if let Some(action) = self.bindings.get(Input::Text(c)) {
+            self.run_action(action, mods);
         }
     }
 
     /// Handle keyboard events
     fn handle_keypress(&mut self, keycode: Keycode, keymod: Mod) {
-        // remove uninteresting mods
-        let keymod = keymod & !(Mod::NUMMOD | Mod::CAPSMOD | Mod::MODEMOD);
-        let nomod = keymod.is_empty();
-        match keycode {
-            Keycode::Escape if nomod => self.quit(),
-
-            // space, backspace: scroll pages, preserve zoom
-            Keycode::Space if nomod => self.scroll(1.),
-            Keycode::Backspace if nomod => self.scroll(-1.),
-
-            Keycode::PageDown => {
-                self.change_file_rel(Self::filelist_step_from_mod(keymod));
+        let mods = keymod.into();
+        if let Some(action) = self.bindings.get(Input::Key(keycode, mods)) {
+            self.run_action(action, mods);
+        }
+    }
+
+    /// Run an action, modifiers may change its step
+    fn run_action(&mut self, action: Action, mods: Modifiers) {
+        if self.gallery.is_some() && self.run_gallery_action(action, mods) {
+            return;
+        }
+        let file_step = Self::filelist_step_from_mods(mods);
+        let move_step = Self::move_step_from_mods(mods);
+        match action {
+            Action::Quit => self.quit(),
+            Action::NextFile if self.compare.is_some() => self.change_compared_rel(file_step),
+            Action::PrevFile if self.compare.is_some() => self.change_compared_rel(-file_step),
+            Action::NextFile => {
+                self.change_file_rel(file_step);
                 self.zoom_adjust();
             },
-            Keycode::PageUp => {
-                self.change_file_rel(-Self::filelist_step_from_mod(keymod));
+            Action::PrevFile => {
+                self.change_file_rel(-file_step);
                 self.zoom_adjust();
             },
-
-            // arrows
-            Keycode::Up => {
-                self.move_rel((0., -Self::move_step_from_mod(keymod)));
-            },
-            Keycode::Down => {
-                self.move_rel((0., Self::move_step_from_mod(keymod)));
-            },
-            Keycode::Right => if self.is_adjusted() {
-                self.change_file_rel(Self::filelist_step_from_mod(keymod));
-                self.zoom_adjust();
+            Action::Right => if self.is_adjusted() {
+                self.run_action(Action::NextFile, mods);
             } else {
-                self.move_rel((Self::move_step_from_mod(keymod), 0.));
+                self.move_rel((move_step, 0.));
             },
-            Keycode::Left => if self.is_adjusted() {
-                self.change_file_rel(-Self::filelist_step_from_mod(keymod));
-                self.zoom_adjust();
+            Action::Left => if self.is_adjusted() {
+                self.run_action(Action::PrevFile, mods);
             } else {
-                self.move_rel((-Self::move_step_from_mod(keymod), 0.));
-            }
-
-            // other actions
-            Keycode::F5 => {
+                self.move_rel((-move_step, 0.));
+            },
+            Action::PanUp => self.move_rel((0., -move_step)),
+            Action::PanDown => self.move_rel((0., move_step)),
+            Action::PanLeft => self.move_rel((-move_step, 0.)),
+            Action::PanRight => self.move_rel((move_step, 0.)),
+            Action::ScrollDown => self.scroll(1.),
+            Action::ScrollUp => self.scroll(-1.),
+            Action::ZoomIn => self.zoom_in(None),
+            Action::ZoomOut => self.zoom_out(None),
+            Action::ZoomAdjust => self.zoom_adjust(),
+            Action::ZoomReset => self.set_zoom(1., None),
+            Action::RotateCw => self.rotate_rel(90),
+            Action::RotateCcw => self.rotate_rel(-90),
+            Action::FlipHorizontal => self.flip(true),
+            Action::FlipVertical => self.flip(false),
+            Action::ToggleAnimation => self.toggle_animation(),
+            Action::PrevFrame => self.step_frame(-1),
+            Action::NextFrame => self.step_frame(1),
+            Action::ToggleFullscreen => self.display.toggle_fullscreen(),
+            Action::ToggleCheckerboard => {
+                self.display.checkerboard = match self.display.checkerboard {
+                    Some(_) => None,
+                    None => Some(self.config.checkerboard()),
+                };
+                self.dirty.set(true);
+            },
+            Action::ToggleAlphaView => self.toggle_alpha_view(),
+            Action::CopyColor => self.copy_picked("color", |info, config| info.hex(&config.inspector)),
+            Action::CopyPosition => self.copy_picked("position", |info, _| format!("{}, {}", info.pos.0, info.pos.1)),
+            Action::CopyPath => self.copy_path(),
+            Action::CopyImage => self.copy_image(),
+            Action::ClearSelection => self.clear_selection(),
+            Action::SaveSelection => self.save_selection(),
+            Action::ToggleHistogram => {
+                self.show_histogram = !self.show_histogram;
+                self.dirty.set(true);
+            },
+            Action::CycleScaleFilter => {
+                self.display.scale_filter = self.display.scale_filter.next();
+                self.dirty.set(true);
+            },
+            Action::RefreshFiles => {
                 let current_file = self.image.as_ref().map(|img| PathBuf::from(&img.image.path));
                 let _ = self.update_filelist(current_file);
-            }
-
-            _ => {},
+            },
+            Action::CycleSort => {
+                let key = self.sort_order.key.next();
+                // reshuffle each time random order is selected
+                self.set_sort_order(SortOrder::new(key, self.sort_order.reverse));
+            },
+            Action::ReverseSort => {
+                let order = SortOrder { reverse: !self.sort_order.reverse, ..self.sort_order };
+                self.set_sort_order(order);
+            },
+            Action::Save => self.save_oriented(),
+            Action::ToggleGallery => self.toggle_gallery(),
+            Action::OpenSelected => {},  // only in the gallery
+            Action::ToggleCompare => self.toggle_compare(),
+            Action::CycleCompareLayout => self.cycle_compare_layout(),
+            Action::SwapCompare => self.swap_compared(),
+            Action::IncreaseAmplification | Action::DecreaseAmplification
+                | Action::IncreaseThreshold | Action::DecreaseThreshold => {
+                let compare = try_some!(self.compare.as_ref());
+                let (amplification, threshold) = (compare.amplification, compare.threshold);
+                match action {
+                    Action::IncreaseAmplification => self.set_diff_params(amplification * 2., threshold),
+                    Action::DecreaseAmplification => self.set_diff_params(amplification / 2., threshold),
+                    Action::IncreaseThreshold => self.set_diff_params(amplification, threshold.saturating_mul(2).max(1)),
+                    _ => self.set_diff_params(amplification, threshold / 2),
+                }
+            },
         }
     }
 
+    /// Run an action in the gallery, return false if it is not specific to the gallery
+    ///
+    /// Actions acting on the displayed image are ignored.
+    fn run_gallery_action(&mut self, action: Action, mods: Modifiers) -> bool {
+        let output_size = self.display.size();
+        let gallery = self.gallery.as_mut().unwrap();
+        let columns = gallery.columns(output_size) as isize;
+        let page = columns * gallery.page_rows(output_size) as isize;
+        let step = Self::filelist_step_from_mods(mods) as isize;
+        let offset = match action {
+            Action::Right | Action::PanRight => step,
+            Action::Left | Action::PanLeft => -step,
+            Action::PanDown => columns,
+            Action::PanUp => -columns,
+            Action::NextFile | Action::ScrollDown => page,
+            Action::PrevFile | Action::ScrollUp => -page,
+            Action::OpenSelected => {
+                self.open_selected();
+                return true;
+            },
+            Action::Quit | Action::ToggleFullscreen | Action::RefreshFiles | Action::CycleSort
+                | Action::ReverseSort | Action::ToggleGallery => return false,
+            _ => return true,
+        };
+        gallery.move_selection(offset, self.files.len(), output_size);
+        self.dirty.set(true);
+        true
+    }
+
     /// Handle mouse wheel events
     fn handle_mousewheel(&mut self, step: (i32, i32), pump: &sdl2::EventPump) {
         let (_, step_y) = step;
 
+        if let Some(gallery) = self.gallery.as_mut() {
+            gallery.scroll_by(-step_y * Self::GALLERY_WHEEL_STEP, self.files.len(), self.display.size());
+            self.dirty.set(true);
+            return;
+        }
+
         let alt_mod = {
             let state = pump.keyboard_state();
             state.is_scancode_pressed(Scancode::LAlt)
@@ -519,7 +1752,7 @@ impl App {
                 let mouse_state = pump.mouse_state();
                 size_as!((mouse_state.x(), mouse_state.y()), f32)
             };
-            let center = self.screen_to_image_pos(mouse_pos);
+            let center = self.screen_to_image_pos(mouse_pos, false);
             if step_y > 0 {
                 self.zoom_in(center);
             } else if step_y < 0 {
@@ -529,33 +1762,60 @@ impl App {
     }
 
     /// Handle mouse click release
-    fn handle_mouse_release(&mut self, button: MouseButton, _clicks: u8, _pos: (i32, i32)) {
-        let dragging = self.image.as_ref().and_then(|i| i.drag).is_some();
-        match button {
-            MouseButton::Left => {
-                if dragging {
-                    let image = self.image.as_mut().unwrap();
-                    image.drag = None;  // end drag
-                } else {
-                    self.change_file_rel(1);
-                    self.zoom_adjust();
-                }
-            },
-            MouseButton::Right => {
-                if dragging {
-                    // ignore click
-                } else {
-                    self.change_file_rel(-1);
-                    self.zoom_adjust();
+    fn handle_mouse_release(&mut self, button: MouseButton, clicks: u8, pos: (i32, i32)) {
+        if self.gallery.is_some() {
+            if button == MouseButton::Left {
+                self.click_gallery(clicks, pos);
+            }
+            return;
+        }
+        if let Some(compare) = self.compare.as_mut() && compare.split_drag {
+            if button == MouseButton::Left {
+                compare.split_drag = false;
+            }
+            return;
+        }
+        if let Some(image) = self.image.as_mut() && let Some(selection) = &mut image.selection && selection.dragging {
+            if button == MouseButton::Left {
+                selection.dragging = false;
+                if selection.rect().is_none() {
+                    image.selection = None;
                 }
-            },
-            _ => {},
+            }
+            return;
+        }
+        if let Some(image) = self.image.as_mut() && image.drag.is_some() {
+            if button == MouseButton::Left {
+                image.drag = None;  // end drag
+            }
+            return;
         }
+        let mods = self.display.sdl_context.keyboard().mod_state().into();
+        if let Some(action) = self.bindings.get(Input::Mouse(button, mods)) {
+            self.run_action(action, mods);
+        }
+    }
+
+    /// Select the clicked thumbnail, open it on double click
+    fn click_gallery(&mut self, clicks: u8, pos: (i32, i32)) {
+        let output_size = self.display.size();
+        let gallery = try_some!(self.gallery.as_mut());
+        gallery.selected = try_some!(gallery.index_at(pos, output_size, self.files.len()));
+        if clicks >= 2 {
+            self.open_selected();
+        }
+        self.dirty.set(true);
     }
 
     /// Handle mouse click press
     fn handle_mouse_move(&mut self, state: MouseState, pos: (i32, i32), pump: &sdl2::EventPump) {
+        if self.gallery.is_some() {
+            return;
+        }
         if state.is_mouse_button_pressed(MouseButton::Left) {
+            if self.drag_split(pos) || self.drag_selection(pos) {
+                return;
+            }
             // Don't muse relative move for better precision
             // Also, it deals better with cursor leaving temporarily the window
             if let Some((x, y)) = self.image.as_ref().and_then(|i| i.drag) {
@@ -567,57 +1827,125 @@ impl App {
         } else {
             let keyboard_state = pump.keyboard_state();
             if keyboard_state.is_scancode_pressed(Scancode::LCtrl) {
-                let pixel_pos = size_as!(try_some!(self.screen_to_image_pos(size_as!(pos, f32))), i32);
-                let image = try_some!(self.image.as_mut());
-                let pixel_color = self.display.draw_pixel_and_get_color(&image.image, pixel_pos).unwrap();
-                image.pixel_info = Some((pixel_pos, pixel_color));
+                // pick the pixel of the image under the cursor: current, compared or difference
+                let pos = size_as!(pos, f32);
+                let pane = try_some!(self.pane_at(pos));
+                let pixel_pos = size_as!(try_some!(pane.screen_to_image(pos)), i32);
+                let picked = try_some!(Self::pane_image(pane.content, &mut self.image, &mut self.compare, self.alpha_view));
+                let info = PixelInfo::new(|pos| picked.pixel(pos), pixel_pos, self.config.inspector_average_size, self.config.loupe_size);
+                try_some!(self.image.as_mut()).pixel_info = Some(try_some!(info));
                 self.dirty.set(true);
             }
         }
     }
 
-    /// Get filelist step from a keyboard modifier
-    const fn filelist_step_from_mod(keymod: Mod) -> i32 {
-        match keymod {
-            Mod::LSHIFTMOD | Mod::RSHIFTMOD => 5,
-            _ => 1,
+    /// Drag a selection rectangle, return true if it is dragged
+    ///
+    /// A new selection is started by dragging with Shift, if the image is not already being panned.
+    fn drag_selection(&mut self, pos: (i32, i32)) -> bool {
+        let selecting = self.image.as_ref().and_then(|i| i.selection.as_ref()).is_some_and(|s| s.dragging);
+        if !selecting {
+            let image_dragged = self.image.as_ref().is_some_and(|i| i.drag.is_some());
+            let mods: Modifiers = self.display.sdl_context.keyboard().mod_state().into();
+            if image_dragged || !mods.shift {
+                return false;
+            }
         }
+        // corners are clamped, to select up to the edges even if the cursor goes past them
+        let Some(image_pos) = self.screen_to_image_pos(size_as!(pos, f32), true) else { return selecting };
+        let image = try_some!(self.image.as_mut(), false);
+        match image.selection.as_mut().filter(|s| s.dragging) {
+            Some(selection) => selection.end = image_pos,
+            None => image.selection = Some(Selection { start: image_pos, end: image_pos, dragging: true }),
+        }
+        self.dirty.set(true);
+        true
     }
 
-    /// Get move step from a keyboard modifier
-    const fn move_step_from_mod(keymod: Mod) -> f32 {
-        match keymod {
-            Mod::LALTMOD | Mod::RALTMOD => 10.,
-            Mod::LSHIFTMOD | Mod::RSHIFTMOD => 500.,
-            _ => 50.,
+    /// Drag the split line of compared images, return true if it is dragged
+    ///
+    /// The line is grabbed if the drag starts near it.
+    fn drag_split(&mut self, pos: (i32, i32)) -> bool {
+        let width = self.display.size().0;
+        let image_dragged = self.image.as_ref().is_some_and(|i| i.drag.is_some());
+        let Some(compare) = self.compare.as_mut().filter(|c| c.layout == CompareLayout::Split) else { return false };
+        let near = (pos.0 - compare.split_x(width) as i32).abs() <= Self::SPLIT_GRAB_DISTANCE;
+        if !compare.split_drag && (image_dragged || !near) {
+            return false;
         }
+        compare.split_drag = true;
+        compare.split = (pos.0 as f32 / width as f32).clamp(0., 1.);
+        self.dirty.set(true);
+        true
     }
 
-    /// Convert screen position to image position
-    fn screen_to_image_pos(&self, pos: (f32, f32)) -> Option<(f32, f32)> {
-        let image = self.image.as_ref()?;
-        let (out_sx, out_sy) = size_as!(self.display.size(), f32);
-        let (pos_x, pos_y) = image.pos;
-        let cx = pos_x + (pos.0 - out_sx / 2.) / self.zoom;
-        let cy = pos_y + (pos.1 - out_sy / 2.) / self.zoom;
-        let (img_sx, img_sy) = size_as!(image.image.size(), f32);
-        if cx < 0. || cx > img_sx || cy < 0. || cy > img_sy {
-            return None;
+    /// Get filelist step from keyboard modifiers
+    const fn filelist_step_from_mods(mods: Modifiers) -> i32 {
+        if mods.shift { 5 } else { 1 }
+    }
+
+    /// Get move step from keyboard modifiers
+    const fn move_step_from_mods(mods: Modifiers) -> f32 {
+        if mods.alt {
+            10.
+        } else if mods.shift {
+            500.
+        } else {
+            50.
         }
-        Some((cx, cy))
     }
-}
 
+    /// Return the view of the current image
+    fn view(&self) -> Option<View> {
+        let image = self.image.as_ref()?;
+        Some(image.view(self.zoom, self.image_output_size()))
+    }
 
-/// Check if a path is an image path (based on extension)
-fn is_image_path(path: &Path) -> bool {
-    const EXTENSIONS: [&str; 10] = [
-        "tga", "bmp", "pnm", "gif", "jpg", "jpeg", "tif", "tiff", "png", "webp",
-    ];
+    /// Convert screen position to current image position
+    ///
+    /// Positions on the compared image are converted to the same relative position on the current image.
+    /// Positions outside the image are clamped to its edges if `clamp` is true, None otherwise.
+    fn screen_to_image_pos(&self, pos: (f32, f32), clamp: bool) -> Option<(f32, f32)> {
+        let pane = self.pane_at(pos)?;
+        let (x, y) = if clamp { pane.screen_to_image_clamped(pos) } else { pane.screen_to_image(pos)? };
+        let (sx, sy) = pane.view.image_size;
+        let (cx, cy) = size_as!(self.image.as_ref()?.image.size(), f32);
+        Some((x * cx / sx, y * cy / sy))
+    }
+}
 
-    if let Some(os_ext) = path.extension() && let Some(ext) = os_ext.to_str() {
-        return EXTENSIONS.contains(&ext.to_lowercase().as_str());
+
+/// Format an aspect ratio, as a decimal and as a fraction if it is simple
+fn format_ratio(width: u32, height: u32) -> String {
+    let gcd = gcd(width, height).max(1);
+    let (a, b) = (width / gcd, height / gcd);
+    let ratio = width as f32 / height as f32;
+    if a.max(b) <= 32 {
+        format!("{ratio:.3} ({a}:{b})")
+    } else {
+        format!("{ratio:.3}")
     }
-    false
 }
 
+/// Return the greatest common divisor of two numbers
+const fn gcd(a: u32, b: u32) -> u32 {
+    if b == 0 { a } else { gcd(b, a % b) }
+}
+
+/// Format statistics of a difference, on a single line
+fn format_diff_stats(stats: &DiffStats, amplification: f32, threshold: u8) -> String {
+    let psnr = if stats.psnr.is_finite() { format!("{:.2} dB", stats.psnr) } else { "∞".to_string() };
+    format!(
+        "difference × {amplification}  threshold: {threshold}  max: {}  mean: {:.3}  PSNR: {psnr}  differing pixels: {} / {} ({:.2} %)",
+        stats.max_error, stats.mean_error,
+        stats.differing, stats.pixel_count,
+        stats.differing as f64 * 100. / stats.pixel_count.max(1) as f64,
+    )
+}
+
+/// Check if a file has been modified recently, and may still be being written
+fn is_recently_modified(path: &Path) -> bool {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+        .and_then(|t| t.elapsed().ok())
+        .is_some_and(|elapsed| elapsed < App::RECENT_WRITE)
+}
@@ -0,0 +1,422 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+use serde::{Deserialize, Deserializer};
+use sdl2::keyboard::{Keycode, Mod};
+use sdl2::mouse::MouseButton;
+
+
+/// Action that can be bound to a key or a mouse button
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Action {
+    Quit,
+    NextFile,
+    PrevFile,
+    /// Next file if the whole image is displayed, pan right otherwise
+    Right,
+    /// Previous file if the whole image is displayed, pan left otherwise
+    Left,
+    PanUp,
+    PanDown,
+    PanLeft,
+    PanRight,
+    /// Scroll one screen forth, preserve zoom
+    ScrollDown,
+    /// Scroll one screen back, preserve zoom
+    ScrollUp,
+    ZoomIn,
+    ZoomOut,
+    ZoomAdjust,
+    ZoomReset,
+    RotateCw,
+    RotateCcw,
+    FlipHorizontal,
+    FlipVertical,
+    ToggleAnimation,
+    PrevFrame,
+    NextFrame,
+    ToggleFullscreen,
+    /// Draw transparent images on a checkerboard, or on the background color
+    ToggleCheckerboard,
+    /// Display the alpha channel as greyscale
+    ToggleAlphaView,
+    ToggleHistogram,
+    /// Copy the color of the picked pixel
+    CopyColor,
+    /// Copy the position of the picked pixel
+    CopyPosition,
+    CopyPath,
+    /// Copy the current image, or the selection
+    CopyImage,
+    ClearSelection,
+    /// Save the selection to a new PNG file
+    SaveSelection,
+    CycleScaleFilter,
+    RefreshFiles,
+    /// Switch to the next sort order
+    CycleSort,
+    ReverseSort,
+    Save,
+    /// Switch between the single image view and the thumbnail gallery
+    ToggleGallery,
+    /// Open the file selected in the gallery
+    OpenSelected,
+    /// Compare the current image with another file, or stop comparing
+    ToggleCompare,
+    /// Switch between side by side and split comparison
+    CycleCompareLayout,
+    /// Swap the current and compared images
+    SwapCompare,
+    /// Double the amplification of differences
+    IncreaseAmplification,
+    /// Halve the amplification of differences
+    DecreaseAmplification,
+    /// Double the threshold under which differences are masked
+    IncreaseThreshold,
+    /// Halve the threshold under which differences are masked
+    DecreaseThreshold,
+}
+
+/// Keyboard modifiers, left and right ones are not distinguished
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Default)]
+pub struct Modifiers {
+    pub ctrl: bool,
+    pub shift: bool,
+    pub alt: bool,
+}
+
+/// Input that can be bound to an action
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Input {
+    /// Key press, with modifiers
+    Key(Keycode, Modifiers),
+    /// Text input, a single character (depends on keyboard layout)
+    Text(char),
+    /// Mouse button click, with modifiers
+    Mouse(MouseButton, Modifiers),
+}
+
+/// Bindings overriding the default ones, `None` to remove a default binding
+pub type BindingOverrides = HashMap<Input, Option<Action>>;
+
+/// Map inputs to actions
+pub struct Bindings {
+    map: HashMap<Input, Action>,
+}
+
+
+impl Action {
+    /// Actions, with their names
+    const NAMES: [(Self, &'static str); 46] = [
+        (Self::Quit, "quit"),
+        (Self::NextFile, "next-file"),
+        (Self::PrevFile, "prev-file"),
+        (Self::Right, "right"),
+        (Self::Left, "left"),
+        (Self::PanUp, "pan-up"),
+        (Self::PanDown, "pan-down"),
+        (Self::PanLeft, "pan-left"),
+        (Self::PanRight, "pan-right"),
+        (Self::ScrollDown, "scroll-down"),
+        (Self::ScrollUp, "scroll-up"),
+        (Self::ZoomIn, "zoom-in"),
+        (Self::ZoomOut, "zoom-out"),
+        (Self::ZoomAdjust, "zoom-adjust"),
+        (Self::ZoomReset, "zoom-reset"),
+        (Self::RotateCw, "rotate-cw"),
+        (Self::RotateCcw, "rotate-ccw"),
+        (Self::FlipHorizontal, "flip-horizontal"),
+        (Self::FlipVertical, "flip-vertical"),
+        (Self::ToggleAnimation, "toggle-animation"),
+        (Self::PrevFrame, "prev-frame"),
+        (Self::NextFrame, "next-frame"),
+        (Self::ToggleFullscreen, "toggle-fullscreen"),
+        (Self::ToggleCheckerboard, "toggle-checkerboard"),
+        (Self::ToggleAlphaView, "toggle-alpha-view"),
+        (Self::ToggleHistogram, "toggle-histogram"),
+        (Self::CopyColor, "copy-color"),
+        (Self::CopyPosition, "copy-position"),
+        (Self::CopyPath, "copy-path"),
+        (Self::CopyImage, "copy-image"),
+        (Self::ClearSelection, "clear-selection"),
+        (Self::SaveSelection, "save-selection"),
+        (Self::CycleScaleFilter, "cycle-scale-filter"),
+        (Self::RefreshFiles, "refresh-files"),
+        (Self::CycleSort, "cycle-sort"),
+        (Self::ReverseSort, "reverse-sort"),
+        (Self::Save, "save"),
+        (Self::ToggleGallery, "toggle-gallery"),
+        (Self::OpenSelected, "open-selected"),
+        (Self::ToggleCompare, "toggle-compare"),
+        (Self::CycleCompareLayout, "cycle-compare-layout"),
+        (Self::SwapCompare, "swap-compare"),
+        (Self::IncreaseAmplification, "increase-amplification"),
+        (Self::DecreaseAmplification, "decrease-amplification"),
+        (Self::IncreaseThreshold, "increase-threshold"),
+        (Self::DecreaseThreshold, "decrease-threshold"),
+    ];
+
+    pub fn name(self) -> &'static str {
+        Self::NAMES.iter().find(|(a, _)| *a == self).unwrap().1
+    }
+}
+
+impl FromStr for Action {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::NAMES.iter()
+            .find(|(_, name)| *name == s)
+            .map(|(action, _)| *action)
+            .ok_or_else(|| format!("unknown action `{s}`"))
+    }
+}
+
+
+impl Modifiers {
+    /// Return true if no modifier is set
+    pub const fn is_empty(&self) -> bool {
+        !(self.ctrl || self.shift || self.alt)
+    }
+
+    /// Return modifiers without the ones used to change action steps
+    pub const fn without_step_mods(self) -> Self {
+        Self { ctrl: self.ctrl, shift: false, alt: false }
+    }
+}
+
+impl From<Mod> for Modifiers {
+    fn from(keymod: Mod) -> Self {
+        Self {
+            ctrl: keymod.intersects(Mod::LCTRLMOD | Mod::RCTRLMOD),
+            shift: keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD),
+            alt: keymod.intersects(Mod::LALTMOD | Mod::RALTMOD),
+        }
+    }
+}
+
+impl fmt::Display for Modifiers {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.ctrl {
+            write!(f, "Ctrl+")?;
+        }
+        if self.shift {
+            write!(f, "Shift+")?;
+        }
+        if self.alt {
+            write!(f, "Alt+")?;
+        }
+        Ok(())
+    }
+}
+
+
+impl Input {
+    const MOUSE_BUTTONS: [(MouseButton, &'static str); 5] = [
+        (MouseButton::Left, "MouseLeft"),
+        (MouseButton::Middle, "MouseMiddle"),
+        (MouseButton::Right, "MouseRight"),
+        (MouseButton::X1, "MouseX1"),
+        (MouseButton::X2, "MouseX2"),
+    ];
+}
+
+impl FromStr for Input {
+    type Err = String;
+
+    /// Parse an input
+    ///
+    /// A single character, without modifiers, is a text input.
+    /// Otherwise, it's a key or mouse button name, prefixed by modifiers (e.g. `Ctrl+Shift+PageDown`).
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut mods = Modifiers::default();
+        let mut name = s;
+        while let Some((prefix, rest)) = name.split_once('+') && !rest.is_empty() {
+            match prefix.to_ascii_lowercase().as_str() {
+                "ctrl" => mods.ctrl = true,
+                "shift" => mods.shift = true,
+                "alt" => mods.alt = true,
+                _ => break,
+            }
+            name = rest;
+        }
+
+        let mut chars = name.chars();
+        if let (Some(c), None) = (chars.next(), chars.next()) && mods.is_empty() {
+            Ok(Self::Text(c))
+        } else if let Some((button, _)) = Self::MOUSE_BUTTONS.iter().find(|(_, n)| n.eq_ignore_ascii_case(name)) {
+            Ok(Self::Mouse(*button, mods))
+        } else if let Some(keycode) = Keycode::from_name(name) {
+            Ok(Self::Key(keycode, mods))
+        } else {
+            Err(format!("invalid key `{s}`"))
+        }
+    }
+}
+
+impl fmt::Display for Input {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Key(keycode, mods) => write!(f, "{mods}{}", keycode.name()),
+            Self::Text(c) => write!(f, "{c}"),
+            Self::Mouse(button, mods) => {
+                let name = Self::MOUSE_BUTTONS.iter().find(|(b, _)| b == button).map_or("?", |(_, n)| n);
+                write!(f, "{mods}{name}")
+            },
+        }
+    }
+}
+
+
+impl Bindings {
+    const DEFAULTS: [(&'static str, Action); 47] = [
+        ("Escape", Action::Quit),
+        ("q", Action::Quit),
+        ("Space", Action::ScrollDown),
+        ("Backspace", Action::ScrollUp),
+        ("PageDown", Action::NextFile),
+        ("PageUp", Action::PrevFile),
+        ("MouseLeft", Action::NextFile),
+        ("MouseRight", Action::PrevFile),
+        ("Right", Action::Right),
+        ("Left", Action::Left),
+        ("Up", Action::PanUp),
+        ("Down", Action::PanDown),
+        ("a", Action::ZoomAdjust),
+        ("z", Action::ZoomReset),
+        ("-", Action::ZoomOut),
+        ("+", Action::ZoomIn),
+        ("r", Action::RotateCw),
+        ("R", Action::RotateCcw),
+        ("h", Action::FlipHorizontal),
+        ("v", Action::FlipVertical),
+        ("p", Action::ToggleAnimation),
+        (",", Action::PrevFrame),
+        (".", Action::NextFrame),
+        ("f", Action::ToggleFullscreen),
+        ("b", Action::ToggleCheckerboard),
+        ("A", Action::ToggleAlphaView),
+        ("H", Action::ToggleHistogram),
+        ("i", Action::CycleScaleFilter),
+        ("F5", Action::RefreshFiles),
+        ("s", Action::CycleSort),
+        ("S", Action::ReverseSort),
+        ("Ctrl+S", Action::Save),
+        ("Ctrl+C", Action::CopyColor),
+        ("Ctrl+P", Action::CopyPosition),
+        ("Ctrl+Shift+C", Action::CopyPath),
+        ("Ctrl+I", Action::CopyImage),
+        ("Ctrl+D", Action::ClearSelection),
+        ("Ctrl+E", Action::SaveSelection),
+        ("g", Action::ToggleGallery),
+        ("Return", Action::OpenSelected),
+        ("c", Action::ToggleCompare),
+        ("C", Action::CycleCompareLayout),
+        ("x", Action::SwapCompare),
+        ("]", Action::IncreaseAmplification),
+        ("[", Action::DecreaseAmplification),
+        ("}", Action::IncreaseThreshold),
+        ("{", Action::DecreaseThreshold),
+    ];
+
+    /// Create default bindings, with overrides
+    pub fn new(overrides: &BindingOverrides) -> Self {
+        let mut map: HashMap<Input, Action> = Self::DEFAULTS.iter()
+            .map(|(input, action)| (input.parse().unwrap(), *action))
+            .collect();
+        for (input, action) in overrides {
+            match action {
+                Some(action) => map.insert(*input, *action),
+                None => map.remove(input),
+            };
+        }
+        Self { map }
+    }
+
+    /// Get the action bound to an input
+    ///
+    /// If there is no exact match for a key, modifiers used to change action steps (Shift, Alt) are ignored.
+    /// Mouse buttons must match exactly: Shift is used to select rectangles, not to change steps.
+    pub fn get(&self, input: Input) -> Option<Action> {
+        let fallback = match input {
+            Input::Key(keycode, mods) => Input::Key(keycode, mods.without_step_mods()),
+            Input::Mouse(..) | Input::Text(_) => input,
+        };
+        self.map.get(&input).or_else(|| self.map.get(&fallback)).copied()
+    }
+}
+
+impl fmt::Display for Bindings {
+    /// Format bindings as a configuration section, grouped by action, unbound actions are commented
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "[bindings]")?;
+        for (action, name) in Action::NAMES {
+            let mut inputs: Vec<String> = self.map.iter()
+                .filter(|(_, a)| **a == action)
+                .map(|(input, _)| input.to_string())
+                .collect();
+            inputs.sort_unstable();
+            if inputs.is_empty() {
+                writeln!(f, "# unbound: {name:?}")?;
+            }
+            for input in inputs {
+                writeln!(f, "{input:?} = {name:?}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+
+impl<'de> Deserialize<'de> for Input {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// Action name in configuration, `none` to remove a binding
+struct ActionName(Option<Action>);
+
+impl<'de> Deserialize<'de> for ActionName {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        if s == "none" {
+            Ok(Self(None))
+        } else {
+            s.parse().map(|a| Self(Some(a))).map_err(serde::de::Error::custom)
+        }
+    }
+}
+
+/// Deserialize binding overrides from a table of inputs and action names
+pub fn deserialize_overrides<'de, D: Deserializer<'de>>(deserializer: D) -> Result<BindingOverrides, D::Error> {
+    let map = HashMap::<Input, ActionName>::deserialize(deserializer)?;
+    Ok(map.into_iter().map(|(input, action)| (input, action.0)).collect())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn step_modifiers_fallback() {
+        let none = Modifiers::default();
+        let shift = Modifiers { shift: true, ..none };
+        let ctrl = Modifiers { ctrl: true, ..none };
+        let bindings = Bindings {
+            map: HashMap::from([
+                (Input::Key(Keycode::Right, none), Action::Right),
+                (Input::Key(Keycode::Right, ctrl), Action::NextFrame),
+                (Input::Mouse(MouseButton::Left, none), Action::NextFile),
+            ]),
+        };
+        assert_eq!(bindings.get(Input::Key(Keycode::Right, shift)), Some(Action::Right));
+        assert_eq!(bindings.get(Input::Key(Keycode::Right, Modifiers { alt: true, ..ctrl })), Some(Action::NextFrame));
+        assert_eq!(bindings.get(Input::Key(Keycode::Left, none)), None);
+        assert_eq!(bindings.get(Input::Mouse(MouseButton::Left, none)), Some(Action::NextFile));
+        // Shift + click is not a file step
+        assert_eq!(bindings.get(Input::Mouse(MouseButton::Left, shift)), None);
+    }
+}
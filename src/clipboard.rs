@@ -0,0 +1,117 @@
+use std::io::{self, Write};
+use std::process::{Command, Stdio};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+use sdl2::event::{Event, EventSender};
+use crate::save;
+
+
+/// Commands copying PNG data from their standard input to the clipboard, tried in order
+const IMAGE_COMMANDS: [(&str, &[&str]); 2] = [
+    ("wl-copy", &["--type", "image/png"]),
+    ("xclip", &["-selection", "clipboard", "-target", "image/png", "-in"]),
+];
+
+/// Copy images to the clipboard in a background thread
+///
+/// SDL clipboard only supports text: external commands are used, for Wayland or X11.
+/// They may take a while, or hang: when a copy is done, an SDL user event is pushed to wake up the main loop.
+pub struct ImageCopier {
+    requests: Sender<CopyRequest>,
+    results: Receiver<CopyResult>,
+    /// SDL event type of wake events
+    pub event_type: u32,
+}
+
+/// Image to copy, `RGBA32` pixels
+struct CopyRequest {
+    name: &'static str,
+    width: u32,
+    height: u32,
+    pixels: Vec<u8>,
+}
+
+/// Outcome of a copy: name and size of the copied image, or error
+pub type CopyResult = (&'static str, Result<(u32, u32), String>);
+
+
+impl ImageCopier {
+    /// Create the copier and start its thread
+    pub fn new(sender: EventSender, event_type: u32) -> Self {
+        let (requests, rx) = mpsc::channel::<CopyRequest>();
+        let (tx, results) = mpsc::channel();
+        thread::spawn(move || {
+            // ends when the copier is dropped
+            for CopyRequest { name, width, height, pixels } in rx {
+                let result = copy_image(width, height, &pixels).map(|_| (width, height));
+                if tx.send((name, result)).is_err() {
+                    return;
+                }
+                let _ = sender.push_event(Event::User {
+                    timestamp: 0,
+                    window_id: 0,
+                    type_: event_type,
+                    code: 0,
+                    data1: std::ptr::null_mut(),
+                    data2: std::ptr::null_mut(),
+                });
+            }
+        });
+        Self { requests, results, event_type }
+    }
+
+    /// Request a copy of `RGBA32` pixels, as a PNG image, described by `name` in results
+    pub fn copy(&self, name: &'static str, width: u32, height: u32, pixels: Vec<u8>) {
+        let _ = self.requests.send(CopyRequest { name, width, height, pixels });
+    }
+
+    /// Return the results of finished copies
+    pub fn results(&self) -> Vec<CopyResult> {
+        self.results.try_iter().collect()
+    }
+}
+
+
+/// Copy `RGBA32` pixels to the clipboard, as a PNG image
+///
+/// Errors name the commands to install, when none is available.
+fn copy_image(width: u32, height: u32, pixels: &[u8]) -> Result<(), String> {
+    let mut data = Vec::new();
+    save::write_png(&mut data, width, height, pixels)?;
+    let mut errors = Vec::new();
+    for (program, args) in IMAGE_COMMANDS {
+        match run_with_input(program, args, &data) {
+            Ok(()) => return Ok(()),
+            Err(None) => {},
+            Err(Some(e)) => errors.push(format!("{program}: {e}")),
+        }
+    }
+    if errors.is_empty() {
+        let programs: Vec<_> = IMAGE_COMMANDS.iter().map(|(program, _)| *program).collect();
+        Err(format!("{} not found, one is required to copy images", programs.join(" or ")))
+    } else {
+        Err(errors.join(", "))
+    }
+}
+
+/// Run a command, write data to its standard input, fail with None if the command is not installed
+///
+/// Clipboard commands keep running in the background, to serve the data.
+fn run_with_input(program: &str, args: &[&str], data: &[u8]) -> Result<(), Option<String>> {
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|e| (e.kind() != io::ErrorKind::NotFound).then(|| e.to_string()))?;
+    // close the input once written, for the command to proceed
+    let written = child.stdin.take().unwrap().write_all(data);
+    let status = child.wait().map_err(|e| Some(e.to_string()))?;
+    written.map_err(|e| Some(e.to_string()))?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(Some(status.to_string()))
+    }
+}
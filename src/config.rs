@@ -0,0 +1,294 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use serde::{Deserialize, Deserializer};
+use sdl2::pixels::Color;
+use crate::bindings::{self, BindingOverrides};
+use crate::display::{Checkerboard, ScaleFilter};
+use crate::inspector::{InspectorField, MAX_SQUARE_SIZE};
+use crate::sort::SortKey;
+use crate::thumbnail::ThumbnailSize;
+
+
+/// Application configuration
+///
+/// It can be loaded from a TOML file. Unset keys keep their default value.
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Initial window size
+    pub window_size: (u32, u32),
+    /// Zoom steps used when zooming in/out (percents in the configuration file)
+    #[serde(deserialize_with = "deserialize_zoom_steps")]
+    pub zoom_steps: Vec<f32>,
+    /// Font size of regular text
+    pub font_size: u16,
+    /// Font size of monospace text
+    pub mono_font_size: u16,
+    pub colors: Colors,
+    /// Draw transparent images on a checkerboard, instead of the background color
+    pub checkerboard: bool,
+    /// Size of checkerboard cells, in pixels
+    pub checkerboard_size: u32,
+    /// Filter used to scale images
+    pub scale_filter: ScaleFilter,
+    /// Information displayed by the pixel inspector, in addition to position and color values
+    pub inspector: Vec<InspectorField>,
+    /// Size of the square of pixels averaged by the pixel inspector
+    #[serde(deserialize_with = "deserialize_square_size")]
+    pub inspector_average_size: u32,
+    /// Size of the square of pixels magnified by the pixel inspector, 0 to disable
+    #[serde(deserialize_with = "deserialize_square_size")]
+    pub loupe_size: u32,
+    /// Number of files to preload before and after the current one
+    pub prefetch_count: usize,
+    /// Memory budget of preloaded images, in MiB
+    pub cache_size: usize,
+    /// Rotate images according to their EXIF orientation
+    pub exif_rotate: bool,
+    /// Additional extensions of image files, for files not recognized by their content
+    pub extensions: Vec<String>,
+    /// Extensions of files to ignore, even if recognized as images
+    pub excluded_extensions: Vec<String>,
+    /// Browse directories recursively
+    pub recursive: bool,
+    /// Maximum depth of subdirectories, in recursive mode
+    pub max_depth: usize,
+    /// Include hidden files and directories, in recursive mode
+    pub show_hidden: bool,
+    /// Watch requested paths, update the file list when files are added or removed
+    pub watch: bool,
+    /// Display new files as soon as they are created
+    pub follow: bool,
+    /// Order of the file list
+    pub sort: SortKey,
+    /// Reverse the order of the file list
+    pub sort_reverse: bool,
+    /// Size of gallery thumbnails
+    pub thumbnail_size: ThumbnailSize,
+    /// Bindings overriding the default ones
+    #[serde(deserialize_with = "bindings::deserialize_overrides")]
+    pub bindings: BindingOverrides,
+}
+
+/// Configurable colors
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Colors {
+    #[serde(deserialize_with = "deserialize_color")]
+    pub background: Color,
+    #[serde(deserialize_with = "deserialize_color")]
+    pub file_info: Color,
+    #[serde(deserialize_with = "deserialize_color")]
+    pub pixel_info: Color,
+    #[serde(deserialize_with = "deserialize_color")]
+    pub selection: Color,
+    #[serde(deserialize_with = "deserialize_color")]
+    pub checkerboard_light: Color,
+    #[serde(deserialize_with = "deserialize_color")]
+    pub checkerboard_dark: Color,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            window_size: (800, 500),
+            zoom_steps: default_zoom_steps(),
+            font_size: 12,
+            mono_font_size: 12,
+            colors: Colors::default(),
+            checkerboard: false,
+            checkerboard_size: 16,
+            scale_filter: ScaleFilter::default(),
+            inspector: vec![InspectorField::Alpha, InspectorField::Hsv, InspectorField::Average],
+            inspector_average_size: 3,
+            loupe_size: 15,
+            prefetch_count: 2,
+            cache_size: 512,
+            exif_rotate: true,
+            extensions: Vec::new(),
+            excluded_extensions: Vec::new(),
+            recursive: false,
+            max_depth: 16,
+            show_hidden: false,
+            watch: true,
+            follow: false,
+            sort: SortKey::default(),
+            sort_reverse: false,
+            thumbnail_size: ThumbnailSize::default(),
+            bindings: BindingOverrides::new(),
+        }
+    }
+}
+
+impl Default for Colors {
+    fn default() -> Self {
+        Self {
+            background: Color::RGB(0, 0, 0),
+            file_info: Color::RGB(0, 255, 0),
+            pixel_info: Color::RGB(255, 0, 255),
+            selection: Color::RGB(255, 255, 255),
+            checkerboard_light: Color::RGB(204, 204, 204),
+            checkerboard_dark: Color::RGB(153, 153, 153),
+        }
+    }
+}
+
+impl Config {
+    const FILE_NAME: &str = "riew.toml";
+
+    /// Return the path of the default configuration file
+    pub fn default_path() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join(Self::FILE_NAME))
+    }
+
+    /// Load configuration from a file
+    ///
+    /// If `optional` is true, a missing file is not an error and default configuration is returned.
+    pub fn load(path: &Path, optional: bool) -> Result<Self, String> {
+        let content = match fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(e) if optional && e.kind() == io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => return Err(format!("{}: {e}", path.display())),
+        };
+        Self::parse(&content).map_err(|e| format!("{}: {e}", path.display()))
+    }
+
+    /// Return the checkerboard drawn behind images, when enabled
+    pub const fn checkerboard(&self) -> Checkerboard {
+        Checkerboard {
+            size: self.checkerboard_size,
+            colors: (self.colors.checkerboard_light, self.colors.checkerboard_dark),
+        }
+    }
+
+    /// Parse configuration from TOML content
+    ///
+    /// Errors report the line and the key of the invalid value.
+    pub fn parse(content: &str) -> Result<Self, String> {
+        let deserializer = toml::Deserializer::parse(content).map_err(|e| format_toml_error(content, &e, None))?;
+        serde_path_to_error::deserialize(deserializer).map_err(|e| {
+            let path = e.path().to_string();
+            format_toml_error(content, e.inner(), Some(&path))
+        })
+    }
+}
+
+
+/// Format a TOML error, with its line and key
+fn format_toml_error(content: &str, error: &toml::de::Error, key: Option<&str>) -> String {
+    let line = error.span().map(|span| content[..span.start].matches('\n').count() + 1);
+    let key = key.filter(|k| *k != ".");
+    match (line, key) {
+        (Some(line), Some(key)) => format!("line {line}, key `{key}`: {}", error.message()),
+        (Some(line), None) => format!("line {line}: {}", error.message()),
+        (None, Some(key)) => format!("key `{key}`: {}", error.message()),
+        (None, None) => error.message().to_string(),
+    }
+}
+
+/// Parse a color from an `#RRGGBB` or `#RRGGBBAA` string
+pub fn parse_color(s: &str) -> Option<Color> {
+    let hex = s.strip_prefix('#')?;
+    if !(hex.len() == 6 || hex.len() == 8) || !hex.is_ascii() {
+        return None;
+    }
+    let component = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+    let alpha = if hex.len() == 8 { component(6)? } else { 255 };
+    Some(Color::RGBA(component(0)?, component(2)?, component(4)?, alpha))
+}
+
+fn deserialize_color<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Color, D::Error> {
+    let s = String::deserialize(deserializer)?;
+    parse_color(&s).ok_or_else(|| serde::de::Error::custom(format!("invalid color `{s}`, expected `#RRGGBB` or `#RRGGBBAA`")))
+}
+
+fn deserialize_zoom_steps<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<f32>, D::Error> {
+    let mut steps = Vec::<f32>::deserialize(deserializer)?;
+    if steps.is_empty() || steps.iter().any(|v| !v.is_finite() || *v <= 0.) {
+        return Err(serde::de::Error::custom("zoom steps must be a non-empty list of positive percents"));
+    }
+    steps.sort_by(f32::total_cmp);
+    steps.dedup();
+    Ok(steps.into_iter().map(|v| v / 100.).collect())
+}
+
+fn deserialize_square_size<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u32, D::Error> {
+    let size = u32::deserialize(deserializer)?;
+    if size > MAX_SQUARE_SIZE {
+        return Err(serde::de::Error::custom(format!("size must be at most {MAX_SQUARE_SIZE} pixels")));
+    }
+    Ok(size)
+}
+
+/// Default zoom steps
+fn default_zoom_steps() -> Vec<f32> {
+    (0..0)
+        .chain((  15..  50).step_by(   7))
+        .chain((  50.. 100).step_by(  10))
+        .chain(( 100.. 200).step_by(  25))
+        .chain(( 200.. 600).step_by( 100))
+        .chain(( 600..1000).step_by( 200))
+        .chain((1000..2000).step_by( 500))
+        .chain((2000..5000).step_by(1000))
+        .map(|v| v as f32 / 100.).collect()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_error(content: &str) -> String {
+        match Config::parse(content) {
+            Ok(_) => panic!("invalid configuration accepted: {content:?}"),
+            Err(e) => e,
+        }
+    }
+
+    #[test]
+    fn parse() {
+        let config = Config::parse("").unwrap();
+        assert_eq!(config.window_size, Config::default().window_size);
+
+        let content = "window_size = [640, 480]\nrecursive = true\n[colors]\nbackground = \"#102030\"\n";
+        let config = Config::parse(content).unwrap();
+        assert_eq!(config.window_size, (640, 480));
+        assert!(config.recursive);
+        assert_eq!(config.colors.background, Color::RGB(0x10, 0x20, 0x30));
+        // unset keys keep their default value
+        assert_eq!(config.colors.file_info, Config::default().colors.file_info);
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(parse_error("a = \n"), "line 1: string values must be quoted, expected literal string");
+        assert!(parse_error("watch = true\nunknown = 1\n").starts_with("line 2, key `unknown`: unknown field `unknown`"));
+        assert_eq!(parse_error("window_size = [1, \"x\"]\n"), "line 1, key `window_size[1]`: invalid type: string \"x\", expected u32");
+        assert_eq!(
+            parse_error("watch = true\n\n[colors]\nbackground = \"#12\"\n"),
+            "line 4, key `colors.background`: invalid color `#12`, expected `#RRGGBB` or `#RRGGBBAA`");
+        assert_eq!(parse_error("loupe_size = 64\n"), "line 1, key `loupe_size`: size must be at most 63 pixels");
+        assert_eq!(Config::parse("loupe_size = 63\ninspector_average_size = 0\n").unwrap().loupe_size, 63);
+    }
+
+    #[test]
+    fn colors() {
+        assert_eq!(parse_color("#102030"), Some(Color::RGBA(0x10, 0x20, 0x30, 0xFF)));
+        assert_eq!(parse_color("#aBcDeF80"), Some(Color::RGBA(0xAB, 0xCD, 0xEF, 0x80)));
+        for s in ["102030", "#10203", "#1020304", "#10203040aa", "#10g030", "#1020é0", ""] {
+            assert_eq!(parse_color(s), None, "{s:?}");
+        }
+    }
+
+    #[test]
+    fn zoom_steps() {
+        let config = Config::parse("zoom_steps = [200, 50, 100, 50, 12.5]\n").unwrap();
+        assert_eq!(config.zoom_steps, [0.125, 0.5, 1., 2.]);
+        for steps in ["[]", "[10, 0]", "[10, -1]", "[nan]", "[inf]"] {
+            assert_eq!(
+                parse_error(&format!("zoom_steps = {steps}\n")),
+                "line 1, key `zoom_steps`: zoom steps must be a non-empty list of positive percents");
+        }
+    }
+}
@@ -0,0 +1,318 @@
+use std::io::{Seek, SeekFrom};
+use std::os::raw::{c_char, c_int};
+use std::path::Path;
+use std::ffi::CString;
+use sdl2::pixels::{Color, PixelFormatEnum};
+use sdl2::surface::SurfaceRef;
+use sdl2::image::ImageRWops;
+use sdl2::rwops::RWops;
+use sdl2::sys::{SDL_RWops, SDL_Surface};
+use crate::exif;
+
+
+/// Image decoded in memory, not yet uploaded to a texture
+///
+/// Unlike SDL surfaces, it can be sent across threads.
+pub struct DecodedImage {
+    pub width: u32,
+    pub height: u32,
+    /// Image frames, more than one for animations
+    pub frames: Vec<Frame>,
+    /// EXIF orientation, if available
+    pub orientation: Option<u16>,
+    /// Reduced-resolution versions of still images, largest first
+    pub levels: Vec<Level>,
+    /// Bits of red, green, blue and alpha channels in the source pixel format, 0 for a missing channel
+    pub channel_bits: [u8; 4],
+}
+
+/// Single frame of a decoded image
+pub struct Frame {
+    /// Pixel data, in `RGBA32` format, without padding
+    pub pixels: Vec<u8>,
+    /// Display duration, in milliseconds (0 for still images)
+    pub delay: u32,
+}
+
+/// Pixel of a decoded image
+#[derive(Clone, Copy, Debug)]
+pub struct Pixel {
+    /// Color, with alpha
+    pub color: Color,
+    /// Bits of red, green, blue and alpha channels in the source image, 0 for a missing channel
+    pub channel_bits: [u8; 4],
+}
+
+/// Image downscaled by a power of two, pixels are averaged
+pub struct Level {
+    pub width: u32,
+    pub height: u32,
+    /// Pixel data, in `RGBA32` format, without padding
+    pub pixels: Vec<u8>,
+}
+
+impl DecodedImage {
+    pub const FORMAT: PixelFormatEnum = PixelFormatEnum::RGBA32;
+    /// Delay used for animation frames without a (sensible) delay, as done by web browsers
+    const DEFAULT_FRAME_DELAY: u32 = 100;
+    /// Size under which no smaller level is built
+    const MIN_LEVEL_SIZE: u32 = 256;
+    /// Channel bits of `FORMAT`, also used for computed images
+    pub const FULL_DEPTH: [u8; 4] = [8; 4];
+
+    /// Decode an image file
+    pub fn load(path: &Path) -> Result<Self, String> {
+        // Provide the extension as type hint, required for formats without magic (e.g. TGA)
+        let ext = path.extension().map(|e| e.to_string_lossy().into_owned()).unwrap_or_default();
+        let mut rwops = RWops::from_file(path, "rb")?;
+
+        let orientation = exif::read_orientation(path);
+
+        if rwops.is_gif() || rwops.is_webp() {
+            match Self::load_animation(&rwops, &ext) {
+                Ok(image) => return Ok(Self { orientation, ..image }),
+                Err(_) => {
+                    // not supported by SDL_image, fallback to a still image
+                    rwops.seek(SeekFrom::Start(0)).map_err(|e| e.to_string())?;
+                }
+            }
+        }
+
+        let surface = rwops.load_typed(&ext)?;
+        let pixels = Self::surface_pixels(&surface)?;
+        Ok(Self {
+            width: surface.width(),
+            height: surface.height(),
+            frames: vec![Frame { pixels, delay: 0 }],
+            orientation,
+            levels: Vec::new(),
+            channel_bits: Self::surface_channel_bits(&surface),
+        })
+    }
+
+    /// Decode all frames of an animated image
+    fn load_animation(rwops: &RWops, ext: &str) -> Result<Self, String> {
+        let c_ext = CString::new(ext).map_err(|e| e.to_string())?;
+        let anim = unsafe { IMG_LoadAnimationTyped_RW(rwops.raw(), 0, c_ext.as_ptr()) };
+        if anim.is_null() {
+            return Err(sdl2::get_error());
+        }
+
+        let result = unsafe {
+            let anim = &*anim;
+            (0..anim.count as usize)
+                .map(|i| {
+                    let surface = SurfaceRef::from_ll(*anim.frames.add(i));
+                    let delay = match *anim.delays.add(i) {
+                        d if d <= 10 => Self::DEFAULT_FRAME_DELAY,
+                        d => d as u32,
+                    };
+                    Ok(Frame { pixels: Self::surface_pixels(surface)?, delay })
+                })
+                .collect::<Result<Vec<_>, String>>()
+                .map(|frames| Self { width: anim.w as u32, height: anim.h as u32, frames, orientation: None, levels: Vec::new(), channel_bits: Self::FULL_DEPTH })
+        };
+        unsafe { IMG_FreeAnimation(anim) };
+
+        let mut image = result?;
+        if image.frames.is_empty() {
+            return Err("animation without frames".to_string());
+        }
+        if image.frames.len() == 1 {
+            image.frames[0].delay = 0;
+        }
+        // frames are decoded to 32-bit surfaces, whatever the source format
+        let has_alpha = image.frames.iter().any(|f| f.pixels.chunks_exact(4).any(|p| p[3] < 255));
+        image.channel_bits[3] = if has_alpha { 8 } else { 0 };
+        Ok(image)
+    }
+
+    /// Copy the pixels of a surface
+    fn surface_pixels(surface: &SurfaceRef) -> Result<Vec<u8>, String> {
+        let surface = surface.convert_format(Self::FORMAT)?;
+        let (width, height) = surface.size();
+        let pitch = surface.pitch() as usize;
+        let row_size = width as usize * 4;
+        let pixels = surface.with_lock(|data| {
+            let mut pixels = Vec::with_capacity(row_size * height as usize);
+            for row in data.chunks(pitch).take(height as usize) {
+                pixels.extend_from_slice(&row[..row_size]);
+            }
+            pixels
+        });
+        Ok(pixels)
+    }
+
+    /// Return the bits of each channel of a surface
+    ///
+    /// Palette colors are 8-bit, with alpha only if the palette or a color key makes pixels transparent.
+    fn surface_channel_bits(surface: &SurfaceRef) -> [u8; 4] {
+        let has_color_key = surface.color_key().is_ok();
+        match surface.pixel_format_enum().into_masks() {
+            Ok(masks) if masks.rmask != 0 => {
+                let bits = [masks.rmask, masks.gmask, masks.bmask, masks.amask].map(|m| m.count_ones() as u8);
+                if bits[3] == 0 && has_color_key { [bits[0], bits[1], bits[2], 8] } else { bits }
+            },
+            _ => {
+                let palette = unsafe { (*(*surface.raw()).format).palette.as_ref() };
+                let colors = palette.map_or(&[][..], |p| unsafe { std::slice::from_raw_parts(p.colors, p.ncolors as usize) });
+                let has_alpha = has_color_key || colors.iter().any(|c| c.a < 255);
+                [8, 8, 8, if has_alpha { 8 } else { 0 }]
+            },
+        }
+    }
+
+    pub const fn size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    /// Return a pixel of a frame, None if outside the image
+    pub fn pixel(&self, frame: usize, (x, y): (i32, i32)) -> Option<Pixel> {
+        if x < 0 || y < 0 || x as u32 >= self.width || y as u32 >= self.height {
+            return None;
+        }
+        let offset = y as usize * self.pitch() + x as usize * 4;
+        let p = &self.frames.get(frame)?.pixels[offset..offset + 4];
+        Some(Pixel { color: Color::RGBA(p[0], p[1], p[2], p[3]), channel_bits: self.channel_bits })
+    }
+
+    /// Return the length of a pixel row, in bytes
+    pub const fn pitch(&self) -> usize {
+        self.width as usize * 4
+    }
+
+    /// Return the memory used by the pixel data, in bytes
+    pub fn memory_size(&self) -> usize {
+        self.frames.iter().map(|f| f.pixels.len()).sum::<usize>()
+            + self.levels.iter().map(|l| l.pixels.len()).sum::<usize>()
+    }
+
+    /// Return a region of a frame, as a still image
+    pub fn crop(&self, frame: usize, (x, y, width, height): (u32, u32, u32, u32)) -> Self {
+        let pixels = self.frames[frame].pixels
+            .chunks_exact(self.pitch())
+            .skip(y as usize)
+            .take(height as usize)
+            .flat_map(|row| &row[x as usize * 4..(x + width) as usize * 4])
+            .copied()
+            .collect();
+        Self {
+            width,
+            height,
+            frames: vec![Frame { pixels, delay: 0 }],
+            orientation: self.orientation,
+            levels: Vec::new(),
+            channel_bits: self.channel_bits,
+        }
+    }
+
+    /// Return the alpha channel of the image, as opaque greyscale
+    pub fn alpha_channel(&self) -> Self {
+        let to_grey = |pixels: &[u8]| pixels.chunks_exact(4).flat_map(|p| [p[3], p[3], p[3], 255]).collect();
+        Self {
+            width: self.width,
+            height: self.height,
+            frames: self.frames.iter().map(|f| Frame { pixels: to_grey(&f.pixels), delay: f.delay }).collect(),
+            orientation: self.orientation,
+            levels: self.levels.iter().map(|l| Level { width: l.width, height: l.height, pixels: to_grey(&l.pixels) }).collect(),
+            channel_bits: Self::FULL_DEPTH,
+        }
+    }
+
+    /// Build reduced-resolution levels, used to display still images zoomed out
+    pub fn build_levels(&mut self) {
+        if self.frames.len() != 1 {
+            return;
+        }
+        let mut levels = Vec::<Level>::new();
+        let (mut width, mut height) = self.size();
+        while width.max(height) > Self::MIN_LEVEL_SIZE {
+            let pixels = levels.last().map_or(&self.frames[0].pixels, |l| &l.pixels);
+            let level = Level::downscale(width, height, pixels);
+            (width, height) = (level.width, level.height);
+            levels.push(level);
+        }
+        self.levels = levels;
+    }
+}
+
+impl Pixel {
+    /// Return the channel values at the bit depth of the source image, 0 for missing channels
+    ///
+    /// Values are exact for depths up to 8 bits, deeper channels were reduced when decoded.
+    pub fn source_values(&self) -> [u16; 4] {
+        let Color { r, g, b, a } = self.color;
+        std::array::from_fn(|i| {
+            let max = (1u32 << self.channel_bits[i]) - 1;
+            (([r, g, b, a][i] as u32 * max + 127) / 255) as u16
+        })
+    }
+
+    /// Return true if a channel of the source image does not have 8 bits
+    pub fn has_custom_depth(&self) -> bool {
+        self.channel_bits.iter().any(|b| !matches!(b, 0 | 8))
+    }
+}
+
+impl Level {
+    /// Halve the size of an image
+    fn downscale(width: u32, height: u32, pixels: &[u8]) -> Self {
+        let (new_width, new_height) = (width.div_ceil(2), height.div_ceil(2));
+        let pixels = downscale_pixels((width, height), pixels, (new_width, new_height));
+        Self { width: new_width, height: new_height, pixels }
+    }
+}
+
+
+/// Reduce the size of `RGBA32` pixels, each pixel is the average of the area it covers
+///
+/// Colors are weighted by alpha, so that transparent pixels don't darken edges.
+pub fn downscale_pixels((width, height): (u32, u32), pixels: &[u8], (new_width, new_height): (u32, u32)) -> Vec<u8> {
+    let pitch = width as usize * 4;
+    // source range covered by a destination pixel, at least one pixel
+    let range = |i: u32, size: u32, new_size: u32| {
+        let start = (i as u64 * size as u64 / new_size as u64) as usize;
+        let end = ((i as u64 + 1) * size as u64 / new_size as u64) as usize;
+        start..end.max(start + 1)
+    };
+    let mut result = Vec::with_capacity(new_width as usize * new_height as usize * 4);
+    for y in 0..new_height {
+        let rows = range(y, height, new_height);
+        for x in 0..new_width {
+            let cols = range(x, width, new_width);
+            let mut sum = [0u64; 4];
+            for row in rows.clone() {
+                for p in pixels[row * pitch + cols.start * 4..row * pitch + cols.end * 4].chunks_exact(4) {
+                    let alpha = p[3] as u64;
+                    sum[0] += p[0] as u64 * alpha;
+                    sum[1] += p[1] as u64 * alpha;
+                    sum[2] += p[2] as u64 * alpha;
+                    sum[3] += alpha;
+                }
+            }
+            let alpha = sum[3];
+            let count = (rows.len() * cols.len()) as u64;
+            // fully transparent: color is irrelevant
+            let average = |c: u64| (c + alpha / 2).checked_div(alpha).unwrap_or(0) as u8;
+            result.extend_from_slice(&[average(sum[0]), average(sum[1]), average(sum[2]), ((alpha + count / 2) / count) as u8]);
+        }
+    }
+    result
+}
+
+
+/// Animation API of SDL_image 2.6, not provided by `sdl2`
+#[repr(C)]
+#[allow(non_camel_case_types)]
+struct IMG_Animation {
+    w: c_int,
+    h: c_int,
+    count: c_int,
+    frames: *mut *mut SDL_Surface,
+    delays: *mut c_int,
+}
+
+unsafe extern "C" {
+    fn IMG_LoadAnimationTyped_RW(src: *mut SDL_RWops, freesrc: c_int, type_: *const c_char) -> *mut IMG_Animation;
+    fn IMG_FreeAnimation(anim: *mut IMG_Animation);
+}
@@ -0,0 +1,154 @@
+use crate::decode::{DecodedImage, Frame};
+
+
+/// Statistics of the difference between two images
+///
+/// Errors are absolute differences of channel values, alpha included.
+#[derive(Clone, Copy, Debug)]
+pub struct DiffStats {
+    pub max_error: u8,
+    pub mean_error: f64,
+    /// Peak signal-to-noise ratio, in dB, infinite for identical images
+    pub psnr: f64,
+    /// Number of pixels with a channel error above the threshold
+    pub differing: usize,
+    pub pixel_count: usize,
+}
+
+/// Compute the per-pixel difference of the first frames of two images
+///
+/// Differences are multiplied by `amplification`.
+/// Pixels whose errors are all below or equal to `threshold` are masked (black).
+pub fn difference(a: &DecodedImage, b: &DecodedImage, amplification: f32, threshold: u8) -> Result<(DecodedImage, DiffStats), String> {
+    if a.size() != b.size() {
+        return Err(format!(
+            "images have different sizes: {} × {} and {} × {}",
+            a.width, a.height, b.width, b.height));
+    }
+
+    let (width, height) = a.size();
+    let (a, b) = (&a.frames[0].pixels, &b.frames[0].pixels);
+    let mut pixels = Vec::with_capacity(a.len());
+    let mut max_error = 0;
+    let mut sum = 0u64;
+    let mut sum_squares = 0u64;
+    let mut differing = 0;
+    for (pa, pb) in a.chunks_exact(4).zip(b.chunks_exact(4)) {
+        let errors: [u8; 4] = std::array::from_fn(|i| pa[i].abs_diff(pb[i]));
+        let pixel_max = errors.into_iter().max().unwrap();
+        max_error = max_error.max(pixel_max);
+        for e in errors {
+            sum += e as u64;
+            sum_squares += e as u64 * e as u64;
+        }
+        if pixel_max > threshold {
+            differing += 1;
+            let amplify = |e: u8| (e as f32 * amplification).round().min(255.) as u8;
+            // alpha errors are shown as gray, when colors are identical
+            let alpha = errors[3];
+            pixels.extend_from_slice(&[amplify(errors[0].max(alpha)), amplify(errors[1].max(alpha)), amplify(errors[2].max(alpha)), 255]);
+        } else {
+            pixels.extend_from_slice(&[0, 0, 0, 255]);
+        }
+    }
+
+    let pixel_count = a.len() / 4;
+    let values = (a.len() as f64).max(1.);
+    let mse = sum_squares as f64 / values;
+    let stats = DiffStats {
+        max_error,
+        mean_error: sum as f64 / values,
+        psnr: if mse == 0. { f64::INFINITY } else { 10. * (255. * 255. / mse).log10() },
+        differing,
+        pixel_count,
+    };
+    let mut image = DecodedImage {
+        width,
+        height,
+        frames: vec![Frame { pixels, delay: 0 }],
+        orientation: None,
+        levels: Vec::new(),
+        channel_bits: DecodedImage::FULL_DEPTH,
+    };
+    image.build_levels();
+    Ok((image, stats))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn image(width: u32, pixels: &[[u8; 4]]) -> DecodedImage {
+        DecodedImage {
+            width,
+            height: pixels.len() as u32 / width,
+            frames: vec![Frame { pixels: pixels.concat(), delay: 0 }],
+            orientation: None,
+            levels: Vec::new(),
+            channel_bits: DecodedImage::FULL_DEPTH,
+        }
+    }
+
+    fn diff_pixels(diff: &DecodedImage) -> Vec<&[u8]> {
+        diff.frames[0].pixels.chunks_exact(4).collect()
+    }
+
+    #[test]
+    fn identical() {
+        let a = image(2, &[[10, 20, 30, 255], [40, 50, 60, 128]]);
+        let (diff, stats) = difference(&a, &a, 10., 0).unwrap();
+        assert_eq!(stats.psnr, f64::INFINITY);
+        assert_eq!((stats.max_error, stats.mean_error), (0, 0.));
+        assert_eq!((stats.differing, stats.pixel_count), (0, 2));
+        assert!(diff_pixels(&diff).iter().all(|p| *p == [0, 0, 0, 255]));
+    }
+
+    #[test]
+    fn size_mismatch() {
+        let a = image(2, &[[0; 4]; 4]);
+        let b = image(4, &[[0; 4]; 4]);
+        let Err(e) = difference(&a, &b, 1., 0) else { panic!("sizes should differ") };
+        assert_eq!(e, "images have different sizes: 2 × 2 and 4 × 1");
+    }
+
+    #[test]
+    fn stats() {
+        let a = image(2, &[[0, 0, 0, 255], [0, 0, 0, 255]]);
+        let b = image(2, &[[0, 0, 0, 255], [255, 0, 0, 255]]);
+        let (_, stats) = difference(&a, &b, 1., 0).unwrap();
+        assert_eq!(stats.max_error, 255);
+        assert_eq!(stats.mean_error, 255. / 8.);
+        // MSE is 255² / 8
+        assert!((stats.psnr - 10. * 8f64.log10()).abs() < 1e-9);
+        assert_eq!(stats.differing, 1);
+    }
+
+    #[test]
+    fn threshold() {
+        let a = image(3, &[[100, 100, 100, 255]; 3]);
+        let b = image(3, &[[102, 100, 100, 255], [100, 103, 100, 255], [100, 100, 110, 255]]);
+        let (diff, stats) = difference(&a, &b, 1., 3).unwrap();
+        // errors equal to the threshold are masked
+        assert_eq!(stats.differing, 1);
+        assert_eq!(diff_pixels(&diff), [&[0, 0, 0, 255], &[0, 0, 0, 255], &[0, 0, 10, 255]]);
+    }
+
+    #[test]
+    fn amplification() {
+        let a = image(2, &[[0, 0, 0, 255], [0, 0, 0, 255]]);
+        let b = image(2, &[[1, 10, 100, 255], [0, 0, 0, 255]]);
+        let (diff, _) = difference(&a, &b, 5., 0).unwrap();
+        // saturated at 255
+        assert_eq!(diff_pixels(&diff)[0], [5, 50, 255, 255]);
+    }
+
+    #[test]
+    fn alpha_only() {
+        let a = image(1, &[[50, 60, 70, 255]]);
+        let b = image(1, &[[50, 60, 70, 215]]);
+        let (diff, stats) = difference(&a, &b, 2., 0).unwrap();
+        assert_eq!(stats.differing, 1);
+        assert_eq!(diff_pixels(&diff)[0], [80, 80, 80, 255]);
+    }
+}
@@ -1,14 +1,19 @@
 use std::rc::Rc;
-use std::path::PathBuf;
+use std::sync::Arc;
+use std::path::Path;
 use sdl2::Sdl;
-use sdl2::pixels::{Color, PixelFormatEnum};
-use sdl2::rect::Rect;
-use sdl2::render::{Texture, TextureCreator, WindowCanvas};
-use sdl2::image::{LoadTexture, ImageRWops};
+use sdl2::pixels::Color;
+use sdl2::rect::{FRect, Rect};
+use sdl2::render::{BlendMode, ScaleMode, Texture, TextureCreator, WindowCanvas};
+use sdl2::image::ImageRWops;
 use sdl2::video::{WindowContext, FullscreenType};
 use sdl2::ttf::{Sdl2TtfContext, Font as TtfFont};
 use sdl2::rwops::RWops;
+use sdl2::messagebox::{self, ButtonData, ClickedButton, MessageBoxButtonFlag, MessageBoxFlag};
 use owning_ref::OwningHandle;
+use serde::Deserialize;
+use crate::decode::{DecodedImage, Pixel};
+use crate::view::View;
 
 type OwnedTexture = OwningHandle<Rc<TextureCreator<WindowContext>>, Box<Texture<'static>>>;
 type OwnedFont = OwningHandle<Rc<Sdl2TtfContext>, Box<TtfFont<'static, 'static>>>;
@@ -16,18 +21,86 @@ type OwnedFont = OwningHandle<Rc<Sdl2TtfContext>, Box<TtfFont<'static, 'static>>
 
 /// Image to be displayed
 ///
-/// The texture is kept with creator to avoid lifetime issues.
+/// The textures are kept with creator to avoid lifetime issues.
+/// Frames larger than the maximum texture size are split into several textures.
+/// Decoded pixels are kept too, to read them without the GPU.
 pub struct Image {
-    texture: OwnedTexture,
+    frames: Vec<Frame>,
+    /// Reduced-resolution versions, used when zoomed out, largest first
+    levels: Vec<Level>,
+    /// Index of the displayed frame
+    frame: usize,
     pub width: u32,
     pub height: u32,
     pub path: String,
+    decoded: Arc<DecodedImage>,
+}
+
+/// Image frame, split into tiles
+struct Frame {
+    tiles: Vec<Tile>,
+    /// Frame delay, in milliseconds
+    delay: u32,
+}
+
+/// Reduced-resolution version of an image
+struct Level {
+    tiles: Vec<Tile>,
+    /// Size of the level relative to the image size
+    scale: (f32, f32),
+}
+
+/// Part of a frame, in its own texture
+struct Tile {
+    texture: OwnedTexture,
+    /// Position and size in the image
+    rect: Rect,
 }
 
 impl Image {
     pub const fn size(&self) -> (u32, u32) {
         (self.width, self.height)
     }
+
+    /// Return the displayed frame
+    fn current_frame(&self) -> &Frame {
+        &self.frames[self.frame]
+    }
+
+    /// Return true if the image has several frames
+    pub fn is_animated(&self) -> bool {
+        self.frames.len() > 1
+    }
+
+    pub fn frame_count(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// Return the index of the displayed frame
+    pub const fn frame(&self) -> usize {
+        self.frame
+    }
+
+    /// Return the delay of the displayed frame, in milliseconds
+    pub fn frame_delay(&self) -> u32 {
+        self.current_frame().delay
+    }
+
+    /// Change the displayed frame, relatively, wrap around
+    pub fn step_frame(&mut self, offset: i32) {
+        let count = self.frames.len() as i32;
+        self.frame = (self.frame as i32 + offset).rem_euclid(count) as usize;
+    }
+
+    /// Change the displayed frame, wrap around
+    pub fn set_frame(&mut self, frame: usize) {
+        self.frame = frame % self.frames.len();
+    }
+
+    /// Return a pixel of the displayed frame, None if outside the image
+    pub fn pixel(&self, pos: (i32, i32)) -> Option<Pixel> {
+        self.decoded.pixel(self.frame, pos)
+    }
 }
 
 /// Manage fonts (each with an "outline" version)
@@ -38,6 +111,61 @@ struct FontManager {
     mono: (OwnedFont, OwnedFont),
 }
 
+/// Filter used to scale images
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ScaleFilter {
+    /// Nearest pixel, crisp pixels
+    Nearest,
+    /// Linear interpolation, smooth
+    Linear,
+    /// Best available filter, usually the same as linear
+    Best,
+    /// Nearest when zoomed in, linear otherwise
+    #[default]
+    Auto,
+}
+
+impl ScaleFilter {
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Nearest => "nearest",
+            Self::Linear => "linear",
+            Self::Best => "best",
+            Self::Auto => "auto",
+        }
+    }
+
+    /// Return the next filter, wrapping around
+    pub const fn next(self) -> Self {
+        match self {
+            Self::Nearest => Self::Linear,
+            Self::Linear => Self::Best,
+            Self::Best => Self::Auto,
+            Self::Auto => Self::Nearest,
+        }
+    }
+
+    /// Return the texture scale mode to use at given zoom
+    const fn scale_mode(self, zoom: f32) -> ScaleMode {
+        match self {
+            Self::Nearest => ScaleMode::Nearest,
+            Self::Linear => ScaleMode::Linear,
+            Self::Best => ScaleMode::Best,
+            Self::Auto if zoom > 1. => ScaleMode::Nearest,
+            Self::Auto => ScaleMode::Linear,
+        }
+    }
+}
+
+/// Checkerboard drawn behind images, to show transparent pixels
+#[derive(Clone, Copy, Debug)]
+pub struct Checkerboard {
+    /// Size of cells, in pixels
+    pub size: u32,
+    pub colors: (Color, Color),
+}
+
 /// List of available fonts, to be used by the display user
 #[derive(Clone, Copy)]
 pub enum Font {
@@ -47,7 +175,7 @@ pub enum Font {
 
 
 impl FontManager {
-    pub fn init() -> Result<Self, String> {
+    pub fn init(size: u16, mono_size: u16) -> Result<Self, String> {
         let ttf_context = sdl2::ttf::init()?;
         let ttf_context = Rc::new(ttf_context);
 
@@ -59,8 +187,8 @@ impl FontManager {
         }
 
         Ok(Self {
-            normal: load_font!(ttf_context, "DejaVuSans.ttf", 12, 1),
-            mono: load_font!(ttf_context, "DejaVuSansMono.ttf", 12, 1),
+            normal: load_font!(ttf_context, "DejaVuSans.ttf", size, 1),
+            mono: load_font!(ttf_context, "DejaVuSansMono.ttf", mono_size, 1),
         })
     }
 
@@ -97,12 +225,17 @@ pub struct Display {
     canvas: WindowCanvas,
     texture_creator: Rc<TextureCreator<WindowContext>>,
     pub bg_color: Color,
+    /// Checkerboard drawn behind images, None to draw them directly on the background color
+    pub checkerboard: Option<Checkerboard>,
+    pub scale_filter: ScaleFilter,
     rendered_textures: Vec<OwnedTexture>,
+    /// Maximum size of textures, larger images are split into tiles
+    max_texture_size: (u32, u32),
 }
 
 
 impl Display {
-    pub fn init(size: (u32, u32)) -> Result<Self, String> {
+    pub fn init(size: (u32, u32), font_sizes: (u16, u16)) -> Result<Self, String> {
         let sdl_context = sdl2::init()?;
         let video_subsystem = sdl_context.video()?;
         let mut window = video_subsystem.window("riew", size.0, size.1)
@@ -113,9 +246,17 @@ impl Display {
         if let Ok(icon) = load_app_icon() {
             window.set_icon(icon);
         }
-        let canvas = window.into_canvas().build().map_err(|e| e.to_string())?;
+        let mut canvas = window.into_canvas().build().map_err(|e| e.to_string())?;
+        // translucent colors are blended, e.g. for overlay panels
+        canvas.set_blend_mode(BlendMode::Blend);
         let texture_creator = Rc::new(canvas.texture_creator());
-        let fonts = FontManager::init()?;
+        let max_texture_size = {
+            // 0 means no limit
+            let info = canvas.info();
+            let or_max = |v: u32| if v == 0 { u32::MAX } else { v };
+            (or_max(info.max_texture_width), or_max(info.max_texture_height))
+        };
+        let fonts = FontManager::init(font_sizes.0, font_sizes.1)?;
 
         Ok(Self {
             sdl_context,
@@ -123,7 +264,10 @@ impl Display {
             canvas,
             texture_creator,
             bg_color: Color::RGB(0, 0, 0),
+            checkerboard: None,
+            scale_filter: ScaleFilter::default(),
             rendered_textures: Vec::new(),
+            max_texture_size,
         })
     }
 
@@ -132,34 +276,154 @@ impl Display {
         self.canvas.output_size().unwrap()
     }
 
-    pub fn load_image(&self, path: &PathBuf) -> Result<Image, String> {
-        let creator = self.texture_creator.clone();
-        let texture = OwningHandle::try_new(creator, |o| -> Result<_, String> {
-            let t = unsafe { (*o).load_texture(path)? };
-            Ok(Box::new(t))
-        })?;
+    /// Create an image from decoded pixels, upload them to textures
+    pub fn create_image(&self, path: &Path, decoded: Arc<DecodedImage>) -> Result<Image, String> {
+        let frames = decoded.frames.iter()
+            .map(|frame| {
+                let tiles = self.create_tiles(decoded.size(), &frame.pixels)?;
+                Ok(Frame { tiles, delay: frame.delay })
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+        let levels = decoded.levels.iter()
+            .map(|level| {
+                let tiles = self.create_tiles((level.width, level.height), &level.pixels)?;
+                let scale = (level.width as f32 / decoded.width as f32, level.height as f32 / decoded.height as f32);
+                Ok(Level { tiles, scale })
+            })
+            .collect::<Result<Vec<_>, String>>()?;
 
-        let query = texture.query();
         let image = Image {
-            texture,
-            width: query.width,
-            height: query.height,
+            frames,
+            levels,
+            frame: 0,
+            width: decoded.width,
+            height: decoded.height,
             path: path.to_string_lossy().into_owned(),
+            decoded,
         };
 
         Ok(image)
     }
 
-    /// Draw an image
-    pub fn draw_image(&mut self, image: &Image, center: (f32, f32), zoom: f32, angle: i32) {
-        let (out_sx, out_sy) = size_as!(self.size(), f32);
-        let (img_sx, img_sy) = size_as!(image.size(), f32);
-        let (dst_sx, dst_sy) = (img_sx * zoom, img_sy * zoom);
-        let dst_x = out_sx / 2. - center.0 * zoom;
-        let dst_y = out_sy / 2. - center.1 * zoom;
+    /// Upload `RGBA32` pixels to textures, split into tiles if needed
+    fn create_tiles(&self, (width, height): (u32, u32), pixels: &[u8]) -> Result<Vec<Tile>, String> {
+        let pitch = width as usize * 4;
+        let (max_sx, max_sy) = self.max_texture_size;
+        let mut tiles = Vec::new();
+        for y in (0..height).step_by(max_sy as usize) {
+            for x in (0..width).step_by(max_sx as usize) {
+                let rect = Rect::new(x as i32, y as i32, max_sx.min(width - x), max_sy.min(height - y));
+                let offset = y as usize * pitch + x as usize * 4;
+                let creator = self.texture_creator.clone();
+                let texture = OwningHandle::try_new(creator, |o| -> Result<_, String> {
+                    let mut t = unsafe { (*o).create_texture_static(DecodedImage::FORMAT, rect.width(), rect.height()).map_err(|e| e.to_string())? };
+                    // rows of the tile are not contiguous, use the pitch of the whole image
+                    t.update(None, &pixels[offset..], pitch).map_err(|e| e.to_string())?;
+                    t.set_blend_mode(BlendMode::Blend);
+                    Ok(Box::new(t))
+                })?;
+                tiles.push(Tile { texture, rect });
+            }
+        }
+        Ok(tiles)
+    }
 
-        let dst = Rect::new(dst_x as i32, dst_y as i32, dst_sx as u32, dst_sy as u32);
-        self.canvas.copy_ex(&image.texture, None, dst, angle as f64, None, false, false).unwrap();
+    /// Draw an image in a part of the display, only its visible tiles
+    ///
+    /// The output size of the view is the size of the viewport, drawing is clipped to it.
+    /// When zoomed out, the smallest level larger than the displayed image is used.
+    pub fn draw_image(&mut self, image: &mut Image, view: &View, viewport: Rect) {
+        let animated = image.is_animated();
+        let level = image.levels.iter_mut().rev()
+            .find(|l| l.scale.0 >= view.zoom && l.scale.1 >= view.zoom)
+            .filter(|_| !animated);
+        let (tiles, (scale_x, scale_y)) = match level {
+            Some(level) => (&mut level.tiles, level.scale),
+            None => (&mut image.frames[image.frame].tiles, (1., 1.)),
+        };
+        let scale_mode = self.scale_filter.scale_mode(view.zoom);
+        self.canvas.set_viewport(viewport);
+        // clip rectangle is relative to the viewport
+        let clip_rect = Rect::new(0, 0, viewport.width(), viewport.height());
+        self.canvas.set_clip_rect(clip_rect);
+        if let Some(checkerboard) = self.checkerboard {
+            Self::draw_checkerboard(&mut self.canvas, &self.texture_creator, &mut self.rendered_textures, checkerboard, view);
+            self.canvas.set_clip_rect(clip_rect);
+        }
+        for tile in tiles {
+            let rect = (
+                tile.rect.x() as f32 / scale_x, tile.rect.y() as f32 / scale_y,
+                tile.rect.width() as f32 / scale_x, tile.rect.height() as f32 / scale_y,
+            );
+            if !view.is_region_visible(rect) {
+                continue;
+            }
+            let (dst_x, dst_y, dst_sx, dst_sy) = view.region_draw_rect(rect);
+            let dst = FRect::new(dst_x, dst_y, dst_sx, dst_sy);
+            tile.texture.set_scale_mode(scale_mode);
+            self.canvas.copy_ex_f(&tile.texture, None, dst, view.normalized_angle() as f64, None, view.flip.0, view.flip.1).unwrap();
+        }
+        self.canvas.set_clip_rect(None);
+        self.canvas.set_viewport(None);
+    }
+
+    /// Draw a checkerboard behind the visible part of an image
+    ///
+    /// Cells are aligned on the output, so that they don't move with the image.
+    fn draw_checkerboard(canvas: &mut WindowCanvas, texture_creator: &Rc<TextureCreator<WindowContext>>, rendered_textures: &mut Vec<OwnedTexture>, checkerboard: Checkerboard, view: &View) {
+        let (ax, ay) = view.image_to_screen((0., 0.));
+        let (bx, by) = view.image_to_screen(view.image_size);
+        let (out_sx, out_sy) = view.output_size;
+        let (x0, y0) = (ax.min(bx).max(0.).floor(), ay.min(by).max(0.).floor());
+        let (x1, y1) = (ax.max(bx).min(out_sx).ceil(), ay.max(by).min(out_sy).ceil());
+        if x0 >= x1 || y0 >= y1 {
+            return;
+        }
+
+        // one pixel per cell, scaled without interpolation
+        let size = checkerboard.size.max(1) as f32;
+        let (cx0, cy0) = ((x0 / size) as i32, (y0 / size) as i32);
+        let (cx1, cy1) = ((x1 / size).ceil() as i32, (y1 / size).ceil() as i32);
+        let (width, height) = ((cx1 - cx0) as u32, (cy1 - cy0) as u32);
+        let (light, dark) = checkerboard.colors;
+        let pixels: Vec<u8> = (cy0..cy1)
+            .flat_map(|cy| (cx0..cx1).map(move |cx| if (cx + cy) % 2 == 0 { light } else { dark }))
+            .flat_map(|c| [c.r, c.g, c.b, 255])
+            .collect();
+        let texture = OwningHandle::try_new(texture_creator.clone(), |o| -> Result<_, String> {
+            let mut t = unsafe { (*o).create_texture_static(DecodedImage::FORMAT, width, height).map_err(|e| e.to_string())? };
+            t.update(None, &pixels, width as usize * 4).map_err(|e| e.to_string())?;
+            t.set_scale_mode(ScaleMode::Nearest);
+            Ok(Box::new(t))
+        }).unwrap();
+
+        canvas.set_clip_rect(Rect::new(x0 as i32, y0 as i32, (x1 - x0) as u32, (y1 - y0) as u32));
+        let dst = FRect::new(cx0 as f32 * size, cy0 as f32 * size, width as f32 * size, height as f32 * size);
+        canvas.copy_f(&texture, None, dst).unwrap();
+        rendered_textures.push(texture);
+    }
+
+    /// Draw an image fitted and centered in a rectangle, never enlarged
+    pub fn draw_image_fit(&mut self, image: &mut Image, rect: Rect) {
+        let scale = 1f32
+            .min(rect.width() as f32 / image.width as f32)
+            .min(rect.height() as f32 / image.height as f32);
+        let x = rect.x() as f32 + (rect.width() as f32 - image.width as f32 * scale) / 2.;
+        let y = rect.y() as f32 + (rect.height() as f32 - image.height as f32 * scale) / 2.;
+        for tile in &mut image.frames[image.frame].tiles {
+            let dst = FRect::new(
+                x + tile.rect.x() as f32 * scale, y + tile.rect.y() as f32 * scale,
+                tile.rect.width() as f32 * scale, tile.rect.height() as f32 * scale,
+            );
+            tile.texture.set_scale_mode(ScaleMode::Linear);
+            self.canvas.copy_f(&tile.texture, None, dst).unwrap();
+        }
+    }
+
+    /// Return the size of rendered text
+    pub fn text_size(&self, font: Font, text: &str) -> (u32, u32) {
+        let (font, _) = self.fonts.get_font(font);
+        font.size_of(text).unwrap_or((0, 0))
     }
 
     /// Draw text
@@ -227,6 +491,21 @@ impl Display {
         self.set_fullscreen(!current);
     }
 
+    /// Copy text to the clipboard
+    pub fn set_clipboard_text(&self, text: &str) -> Result<(), String> {
+        self.canvas.window().subsystem().clipboard().set_clipboard_text(text)
+    }
+
+    /// Ask the user for confirmation, return true if accepted
+    pub fn confirm(&self, title: &str, message: &str) -> bool {
+        let buttons = [
+            ButtonData { flags: MessageBoxButtonFlag::RETURNKEY_DEFAULT, button_id: 1, text: "OK" },
+            ButtonData { flags: MessageBoxButtonFlag::ESCAPEKEY_DEFAULT, button_id: 0, text: "Cancel" },
+        ];
+        let result = messagebox::show_message_box(MessageBoxFlag::WARNING, &buttons, title, message, self.canvas.window(), None);
+        matches!(result, Ok(ClickedButton::CustomButton(button)) if button.button_id == 1)
+    }
+
     /// Change background color brightness
     pub fn set_bg_brightness_rel(&mut self, offset: f32) {
         let mut color = self.bg_color;
@@ -250,20 +529,26 @@ impl Display {
         self.canvas.fill_rect(rect).unwrap();
     }
 
-    /// Draw a single pixel from an image and return its color 
-    pub fn draw_pixel_and_get_color(&mut self, image: &Image, pos: (i32, i32)) -> Result<Color, String> {
-        // Only render targets can be read, that's why we need to draw the pixel.
-        // And the texture cannot be drawn to a new, blank surface.
-        self.canvas.copy(&image.texture, Rect::new(pos.0, pos.1, 1, 1), Rect::new(0, 0, 1, 1))?;
-        let pixels = self.canvas.read_pixels(None, PixelFormatEnum::RGBA32)?;
-        Ok(Color::RGB(pixels[0], pixels[1], pixels[2]))
+    /// Draw filled rectangles, of the same color
+    pub fn draw_rectangles(&mut self, rects: &[Rect], color: Color) {
+        self.canvas.set_draw_color(color);
+        self.canvas.fill_rects(rects).unwrap();
+    }
+
+    /// Draw the outline of a rectangle, inside it
+    pub fn draw_outline(&mut self, rect: Rect, width: u32, color: Color) {
+        self.canvas.set_draw_color(color);
+        for i in 0..width.min(rect.width() / 2).min(rect.height() / 2) {
+            let r = Rect::new(rect.x() + i as i32, rect.y() + i as i32, rect.width() - 2 * i, rect.height() - 2 * i);
+            self.canvas.draw_rect(r).unwrap();
+        }
     }
 }
 
 
 /// Load the application icon
 fn load_app_icon() -> Result<sdl2::surface::Surface<'static>, String> {
-    const ICON_DATA: &'static [u8] = include_bytes!("../res/icon.png");
+    const ICON_DATA: &[u8] = include_bytes!("../res/icon.png");
 
     RWops::from_bytes(ICON_DATA)?.load_png()
 }
@@ -0,0 +1,461 @@
+use std::fs::File;
+use std::io::{self, Cursor, Read, Seek, SeekFrom};
+use std::ops::Range;
+use std::path::Path;
+
+
+/// Location and value of an EXIF orientation tag
+struct OrientationTag {
+    /// File offset of the tag value
+    offset: u64,
+    big_endian: bool,
+    /// Orientation value, from 1 to 8
+    value: u16,
+}
+
+const ORIENTATION_TAG_ID: u16 = 0x0112;
+const TYPE_SHORT: u16 = 3;
+
+/// Read the EXIF orientation of an image file
+pub fn read_orientation(path: &Path) -> Option<u16> {
+    find_orientation(path).ok().flatten().map(|tag| tag.value)
+}
+
+/// Return the data of an image file, with its EXIF orientation set
+///
+/// An existing tag is updated in place. Otherwise, the tag is added to IFD0,
+/// and JPEG and extended WebP files without EXIF data are given new EXIF data.
+pub fn set_orientation(data: &[u8], value: u16) -> Result<Vec<u8>, String> {
+    if let Some(tag) = find_orientation_in(&mut Cursor::new(data)).map_err(|e| e.to_string())? {
+        let bytes = if tag.big_endian { value.to_be_bytes() } else { value.to_le_bytes() };
+        let mut data = data.to_vec();
+        let offset = tag.offset as usize;
+        data[offset..offset + 2].copy_from_slice(&bytes);
+        return Ok(data);
+    }
+
+    if data.starts_with(&[0xFF, 0xD8]) {
+        set_jpeg_orientation(data, value)
+    } else if data.starts_with(b"II*\0") || data.starts_with(b"MM\0*") {
+        add_orientation_entry(data, value)
+    } else if data.starts_with(b"RIFF") && data.get(8..12) == Some(b"WEBP") {
+        set_webp_orientation(data, value)
+    } else {
+        Err("EXIF orientation is not supported for this format".to_string())
+    }
+}
+
+/// Return the EXIF orientation value of a view transform (see `orientation_transform()`)
+pub const fn orientation_value(angle: i32, flip: (bool, bool)) -> u16 {
+    // a vertical flip is a horizontal flip followed by a half turn
+    let (angle, flip_h) = if flip.1 { (angle + 180, !flip.0) } else { (angle, flip.0) };
+    match (angle.rem_euclid(360), flip_h) {
+        (0, true) => 2,
+        (180, false) => 3,
+        (180, true) => 4,
+        (270, true) => 5,
+        (90, false) => 6,
+        (90, true) => 7,
+        (270, false) => 8,
+        _ => 1,
+    }
+}
+
+/// Locate the EXIF orientation tag of an image file
+///
+/// Supported containers: JPEG (APP1 segment), TIFF, WebP (EXIF chunk).
+fn find_orientation(path: &Path) -> io::Result<Option<OrientationTag>> {
+    find_orientation_in(&mut File::open(path)?)
+}
+
+/// Locate the EXIF orientation tag of image data
+fn find_orientation_in<R: Read + Seek>(file: &mut R) -> io::Result<Option<OrientationTag>> {
+    let mut magic = [0u8; 12];
+    file.read_exact(&mut magic)?;
+
+    if magic.starts_with(&[0xFF, 0xD8]) {
+        find_in_jpeg(file)
+    } else if magic.starts_with(b"II*\0") || magic.starts_with(b"MM\0*") {
+        find_in_tiff(file, 0)
+    } else if magic.starts_with(b"RIFF") && &magic[8..12] == b"WEBP" {
+        find_in_webp(file)
+    } else {
+        Ok(None)
+    }
+}
+
+/// Return the view transform for an orientation value
+///
+/// Return the clockwise rotation angle, in degrees, and whether to flip horizontally.
+/// Flip is applied before the rotation.
+pub const fn orientation_transform(value: u16) -> (i32, bool) {
+    match value {
+        2 => (0, true),
+        3 => (180, false),
+        4 => (180, true),
+        5 => (270, true),
+        6 => (90, false),
+        7 => (90, true),
+        8 => (270, false),
+        _ => (0, false),
+    }
+}
+
+
+fn find_in_jpeg<R: Read + Seek>(file: &mut R) -> io::Result<Option<OrientationTag>> {
+    let mut pos = 2;
+    loop {
+        file.seek(SeekFrom::Start(pos))?;
+        let mut header = [0u8; 4];
+        file.read_exact(&mut header)?;
+        if header[0] != 0xFF {
+            return Ok(None);  // not a marker, invalid file
+        }
+        let marker = header[1];
+        let length = u16::from_be_bytes([header[2], header[3]]) as u64;
+        match marker {
+            // start of scan or end of image: no more metadata
+            0xDA | 0xD9 => return Ok(None),
+            0xE1 => {
+                let mut ident = [0u8; 6];
+                file.read_exact(&mut ident)?;
+                if &ident == b"Exif\0\0" {
+                    return find_in_tiff(file, pos + 10);
+                }
+            },
+            _ => {},
+        }
+        pos += 2 + length;
+    }
+}
+
+fn find_in_webp<R: Read + Seek>(file: &mut R) -> io::Result<Option<OrientationTag>> {
+    let mut pos = 12;
+    loop {
+        file.seek(SeekFrom::Start(pos))?;
+        let mut header = [0u8; 8];
+        match file.read_exact(&mut header) {
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),  // no EXIF chunk
+            result => result?,
+        }
+        let size = u32::from_le_bytes([header[4], header[5], header[6], header[7]]) as u64;
+        if &header[..4] == b"EXIF" {
+            // some writers keep the JPEG APP1 identifier
+            let mut ident = [0u8; 6];
+            file.read_exact(&mut ident)?;
+            let base = if &ident == b"Exif\0\0" { pos + 14 } else { pos + 8 };
+            return find_in_tiff(file, base);
+        }
+        // chunks are padded to an even size
+        pos += 8 + size + (size & 1);
+    }
+}
+
+/// Find the orientation in TIFF data starting at `base`
+fn find_in_tiff<R: Read + Seek>(file: &mut R, base: u64) -> io::Result<Option<OrientationTag>> {
+    file.seek(SeekFrom::Start(base))?;
+    let mut header = [0u8; 8];
+    file.read_exact(&mut header)?;
+    let big_endian = match &header[..4] {
+        b"II*\0" => false,
+        b"MM\0*" => true,
+        _ => return Ok(None),
+    };
+    let read_u16 = |b: &[u8]| if big_endian { u16::from_be_bytes([b[0], b[1]]) } else { u16::from_le_bytes([b[0], b[1]]) };
+    let read_u32 = |b: &[u8]| if big_endian { u32::from_be_bytes([b[0], b[1], b[2], b[3]]) } else { u32::from_le_bytes([b[0], b[1], b[2], b[3]]) };
+
+    // only IFD0 is checked, that's where the orientation is
+    let ifd = base + read_u32(&header[4..]) as u64;
+    file.seek(SeekFrom::Start(ifd))?;
+    let mut count = [0u8; 2];
+    file.read_exact(&mut count)?;
+    let count = read_u16(&count) as usize;
+    let mut entries = vec![0u8; count * 12];
+    file.read_exact(&mut entries)?;
+
+    for (i, entry) in entries.chunks_exact(12).enumerate() {
+        if read_u16(&entry[0..]) == ORIENTATION_TAG_ID && read_u16(&entry[2..]) == TYPE_SHORT {
+            let value = read_u16(&entry[8..]);
+            let offset = ifd + 2 + (i * 12) as u64 + 8;
+            return Ok(Some(OrientationTag { offset, big_endian, value }));
+        }
+    }
+    Ok(None)
+}
+
+/// Location of EXIF data in a JPEG file
+enum JpegExif {
+    /// APP1 segment, at given position and with given length (marker excluded)
+    Segment(usize, usize),
+    /// No EXIF data, a segment can be inserted at given position
+    Missing(usize),
+}
+
+/// Locate the EXIF segment of a JPEG file, None if the file is invalid
+///
+/// A new segment is inserted after the JFIF APP0 segment, if any, which must come first.
+fn find_jpeg_exif(data: &[u8]) -> Option<JpegExif> {
+    if !data.starts_with(&[0xFF, 0xD8]) {
+        return None;
+    }
+    let mut insert_pos = 2;
+    let mut pos = 2;
+    while pos + 4 <= data.len() && data[pos] == 0xFF {
+        let marker = data[pos + 1];
+        let length = u16::from_be_bytes([data[pos + 2], data[pos + 3]]) as usize;
+        match marker {
+            0xDA | 0xD9 => break,
+            0xE0 if pos == 2 => insert_pos = pos + 2 + length,
+            0xE1 if data[pos + 4..].starts_with(b"Exif\0\0") => return Some(JpegExif::Segment(pos, length)),
+            _ => {},
+        }
+        pos += 2 + length;
+    }
+    Some(JpegExif::Missing(insert_pos))
+}
+
+/// Set the orientation of a JPEG file without orientation tag
+fn set_jpeg_orientation(data: &[u8], value: u16) -> Result<Vec<u8>, String> {
+    let (pos, end, tiff) = match find_jpeg_exif(data).ok_or("invalid JPEG file")? {
+        JpegExif::Segment(pos, length) => {
+            let end = pos + 2 + length;
+            let tiff = data.get(pos + 10..end).ok_or("invalid EXIF segment")?;
+            (pos, end, add_orientation_entry(tiff, value)?)
+        },
+        JpegExif::Missing(pos) => (pos, pos, new_exif_data(value)),
+    };
+    // marker excluded, length included
+    let length = u16::try_from(tiff.len() + 8).map_err(|_| "EXIF data too large")?;
+    let mut new_data = Vec::with_capacity(data.len() + tiff.len() + 10);
+    new_data.extend_from_slice(&data[..pos]);
+    new_data.extend_from_slice(&[0xFF, 0xE1]);
+    new_data.extend_from_slice(&length.to_be_bytes());
+    new_data.extend_from_slice(b"Exif\0\0");
+    new_data.extend_from_slice(&tiff);
+    new_data.extend_from_slice(&data[end..]);
+    Ok(new_data)
+}
+
+/// Set the orientation of a WebP file without orientation tag
+///
+/// Simple WebP files (without VP8X chunk) cannot hold EXIF data.
+fn set_webp_orientation(data: &[u8], value: u16) -> Result<Vec<u8>, String> {
+    let mut vp8x = None;
+    let mut xmp = None;
+    let mut pos = 12;
+    while pos + 8 <= data.len() {
+        let size = u32::from_le_bytes([data[pos + 4], data[pos + 5], data[pos + 6], data[pos + 7]]) as usize;
+        // chunks are padded to an even size
+        let end = (pos + 8 + size + (size & 1)).min(data.len());
+        match &data[pos..pos + 4] {
+            b"EXIF" => {
+                let body = data.get(pos + 8..pos + 8 + size).ok_or("invalid EXIF chunk")?;
+                // some writers keep the JPEG APP1 identifier
+                let (ident, tiff) = body.split_at(if body.starts_with(b"Exif\0\0") { 6 } else { 0 });
+                let mut new_body = ident.to_vec();
+                new_body.extend_from_slice(&add_orientation_entry(tiff, value)?);
+                return Ok(replace_webp_chunk(data, pos..end, b"EXIF", &new_body));
+            },
+            b"VP8X" => vp8x = Some(pos),
+            b"XMP " => xmp = xmp.or(Some(pos)),
+            _ => {},
+        }
+        pos = end;
+    }
+
+    let vp8x = vp8x.ok_or("cannot add EXIF data to a simple WebP file")?;
+    if data.len() < vp8x + 18 {
+        return Err("invalid VP8X chunk".to_string());
+    }
+    // EXIF comes before XMP metadata, after image data
+    let pos = xmp.unwrap_or(data.len());
+    let mut new_data = replace_webp_chunk(data, pos..pos, b"EXIF", &new_exif_data(value));
+    new_data[vp8x + 8] |= 0x08;  // EXIF flag
+    Ok(new_data)
+}
+
+/// Replace a range of a WebP file with a chunk, update the RIFF size
+fn replace_webp_chunk(data: &[u8], range: Range<usize>, fourcc: &[u8; 4], body: &[u8]) -> Vec<u8> {
+    let mut new_data = Vec::with_capacity(data.len() + body.len() + 9);
+    new_data.extend_from_slice(&data[..range.start]);
+    new_data.extend_from_slice(fourcc);
+    new_data.extend_from_slice(&(body.len() as u32).to_le_bytes());
+    new_data.extend_from_slice(body);
+    if body.len() % 2 == 1 {
+        new_data.push(0);
+    }
+    new_data.extend_from_slice(&data[range.end..]);
+    let riff_size = (new_data.len() - 8) as u32;
+    new_data[4..8].copy_from_slice(&riff_size.to_le_bytes());
+    new_data
+}
+
+/// Return TIFF data with a single orientation entry
+fn new_exif_data(value: u16) -> Vec<u8> {
+    let mut data = Vec::with_capacity(26);
+    // TIFF header, big-endian, IFD0 right after it
+    data.extend_from_slice(b"MM\0*\0\0\0\x08");
+    // IFD0 with a single orientation entry, no next IFD
+    data.extend_from_slice(&1u16.to_be_bytes());
+    data.extend_from_slice(&ORIENTATION_TAG_ID.to_be_bytes());
+    data.extend_from_slice(&TYPE_SHORT.to_be_bytes());
+    data.extend_from_slice(&1u32.to_be_bytes());
+    data.extend_from_slice(&value.to_be_bytes());
+    data.extend_from_slice(&[0, 0, 0, 0, 0, 0]);
+    data
+}
+
+/// Return TIFF data with an orientation entry added to IFD0
+///
+/// A new IFD0 is appended, the old one is left unused: offsets to other data are unchanged.
+fn add_orientation_entry(tiff: &[u8], value: u16) -> Result<Vec<u8>, String> {
+    let invalid = || "invalid EXIF data".to_string();
+    let big_endian = match tiff.get(..4) {
+        Some(b"II*\0") => false,
+        Some(b"MM\0*") => true,
+        _ => return Err(invalid()),
+    };
+    let read_u16 = |b: &[u8]| if big_endian { u16::from_be_bytes([b[0], b[1]]) } else { u16::from_le_bytes([b[0], b[1]]) };
+    let read_u32 = |b: &[u8]| if big_endian { u32::from_be_bytes([b[0], b[1], b[2], b[3]]) } else { u32::from_le_bytes([b[0], b[1], b[2], b[3]]) };
+    let u16_bytes = |v: u16| if big_endian { v.to_be_bytes() } else { v.to_le_bytes() };
+    let u32_bytes = |v: u32| if big_endian { v.to_be_bytes() } else { v.to_le_bytes() };
+
+    let ifd = read_u32(tiff.get(4..8).ok_or_else(invalid)?) as usize;
+    let count = read_u16(tiff.get(ifd..ifd + 2).ok_or_else(invalid)?) as usize;
+    let entries_end = ifd + 2 + count * 12;
+    let entries = tiff.get(ifd + 2..entries_end).ok_or_else(invalid)?;
+    let next_ifd = tiff.get(entries_end..entries_end + 4).ok_or_else(invalid)?;
+
+    let mut orientation = Vec::with_capacity(12);
+    orientation.extend_from_slice(&u16_bytes(ORIENTATION_TAG_ID));
+    orientation.extend_from_slice(&u16_bytes(TYPE_SHORT));
+    orientation.extend_from_slice(&u32_bytes(1));
+    orientation.extend_from_slice(&u16_bytes(value));
+    orientation.extend_from_slice(&[0, 0]);
+    // entries are sorted by tag, drop an orientation with an unexpected type
+    let mut entries: Vec<&[u8]> = entries.chunks_exact(12).filter(|e| read_u16(e) != ORIENTATION_TAG_ID).collect();
+    let pos = entries.partition_point(|e| read_u16(e) < ORIENTATION_TAG_ID);
+    entries.insert(pos, &orientation);
+
+    let mut new_tiff = tiff.to_vec();
+    // IFDs start on a word boundary
+    if new_tiff.len() % 2 == 1 {
+        new_tiff.push(0);
+    }
+    let new_ifd = u32::try_from(new_tiff.len()).map_err(|_| invalid())?;
+    new_tiff[4..8].copy_from_slice(&u32_bytes(new_ifd));
+    new_tiff.extend_from_slice(&u16_bytes(entries.len() as u16));
+    for entry in entries {
+        new_tiff.extend_from_slice(entry);
+    }
+    new_tiff.extend_from_slice(next_ifd);
+    Ok(new_tiff)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Return the orientation read back from image data
+    fn orientation(data: &[u8]) -> Option<u16> {
+        find_orientation_in(&mut Cursor::new(data)).unwrap().map(|tag| tag.value)
+    }
+
+    /// Return little-endian TIFF data with an IFD0 holding a single entry of given tag
+    fn tiff_without_orientation(tag: u16) -> Vec<u8> {
+        let mut data = b"II*\0\x08\0\0\0".to_vec();
+        data.extend_from_slice(&1u16.to_le_bytes());
+        data.extend_from_slice(&tag.to_le_bytes());
+        data.extend_from_slice(&TYPE_SHORT.to_le_bytes());
+        data.extend_from_slice(&1u32.to_le_bytes());
+        data.extend_from_slice(&[1, 0, 0, 0, 0, 0, 0, 0]);
+        data
+    }
+
+    #[test]
+    fn updates_existing_tag() {
+        let data = set_orientation(&tiff_without_orientation(0x0100), 6).unwrap();
+        let updated = set_orientation(&data, 3).unwrap();
+        assert_eq!(updated.len(), data.len());
+        assert_eq!(orientation(&updated), Some(3));
+    }
+
+    #[test]
+    fn adds_tag_to_tiff() {
+        for tag in [0x0100, 0x8769] {
+            let data = set_orientation(&tiff_without_orientation(tag), 8).unwrap();
+            assert_eq!(orientation(&data), Some(8));
+            // other entries are kept, sorted by tag
+            let count = u16::from_le_bytes([data[26], data[27]]);
+            let tags: Vec<u16> = (0..count as usize).map(|i| u16::from_le_bytes([data[28 + i * 12], data[29 + i * 12]])).collect();
+            let mut sorted = vec![tag, ORIENTATION_TAG_ID];
+            sorted.sort();
+            assert_eq!(tags, sorted);
+        }
+    }
+
+    #[test]
+    fn adds_tag_to_jpeg() {
+        let jfif = [0xFF, 0xD8, 0xFF, 0xE0, 0x00, 0x04, 0x00, 0x00, 0xFF, 0xDA, 0x00, 0x02, 0xFF, 0xD9];
+        let data = set_orientation(&jfif, 6).unwrap();
+        assert_eq!(orientation(&data), Some(6));
+        // EXIF segment inserted after the JFIF one
+        assert_eq!(&data[..6], &jfif[..6]);
+        assert_eq!(&data[8..10], &[0xFF, 0xE1]);
+        assert!(data.ends_with(&jfif[8..]));
+
+        // EXIF segment without orientation
+        let mut exif_jpeg = jfif[..8].to_vec();
+        let tiff = tiff_without_orientation(0x0100);
+        exif_jpeg.extend_from_slice(&[0xFF, 0xE1]);
+        exif_jpeg.extend_from_slice(&(tiff.len() as u16 + 8).to_be_bytes());
+        exif_jpeg.extend_from_slice(b"Exif\0\0");
+        exif_jpeg.extend_from_slice(&tiff);
+        exif_jpeg.extend_from_slice(&jfif[8..]);
+        let data = set_orientation(&exif_jpeg, 5).unwrap();
+        assert_eq!(orientation(&data), Some(5));
+        assert!(data.ends_with(&jfif[8..]));
+    }
+
+    #[test]
+    fn adds_tag_to_webp() {
+        let mut webp = b"RIFF\0\0\0\0WEBPVP8X\x0A\0\0\0".to_vec();
+        webp.extend_from_slice(&[0; 10]);
+        webp.extend_from_slice(b"VP8L\x03\0\0\0abc\0");
+        let size = (webp.len() - 8) as u32;
+        webp[4..8].copy_from_slice(&size.to_le_bytes());
+
+        let data = set_orientation(&webp, 7).unwrap();
+        assert_eq!(orientation(&data), Some(7));
+        assert_eq!(data[20] & 0x08, 0x08);
+        assert_eq!(u32::from_le_bytes([data[4], data[5], data[6], data[7]]) as usize, data.len() - 8);
+        let updated = set_orientation(&data, 2).unwrap();
+        assert_eq!(updated.len(), data.len());
+        assert_eq!(orientation(&updated), Some(2));
+
+        // truncated VP8X chunk
+        assert!(set_orientation(&webp[..24], 7).is_err());
+
+        // no VP8X chunk to flag EXIF data
+        let simple = [&webp[..12], &webp[30..]].concat();
+        assert!(set_orientation(&simple, 7).is_err());
+    }
+
+    #[test]
+    fn truncated_tiff_header() {
+        for len in 0..8 {
+            assert!(add_orientation_entry(&b"II*\0\x08\0\0\0"[..len], 1).is_err());
+        }
+
+        let mut jpeg = vec![0xFF, 0xD8, 0xFF, 0xE1, 0x00, 0x0D];
+        jpeg.extend_from_slice(b"Exif\0\0II*\0\x08");
+        jpeg.extend_from_slice(&[0xFF, 0xD9]);
+        assert!(set_orientation(&jpeg, 6).is_err());
+
+        let mut webp = b"RIFF\0\0\0\0WEBPVP8X\x0A\0\0\0".to_vec();
+        webp.extend_from_slice(&[0; 10]);
+        webp.extend_from_slice(b"EXIF\x05\0\0\0MM\0*\0\0");
+        assert!(set_orientation(&webp, 6).is_err());
+    }
+}
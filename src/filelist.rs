@@ -0,0 +1,140 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use crate::config::Config;
+use crate::format::Formats;
+use crate::save;
+
+
+/// List image files from requested paths
+///
+/// Directories are read according to configuration: only their direct entries, or recursively.
+/// Missing paths are ignored.
+pub fn list_files(paths: &[PathBuf], config: &Config, formats: &Formats) -> Result<Vec<PathBuf>, String> {
+    let mut files = Vec::<PathBuf>::new();
+    for path in paths {
+        if path.as_os_str().is_empty() || path.is_dir() {
+            if config.recursive {
+                let mut visited = HashSet::new();
+                walk_dir(path, config, formats, 0, &mut visited, &mut files)?;
+            } else {
+                for entry in fs::read_dir(path).map_err(|e| e.to_string())? {
+                    let entry_path = entry.map_err(|e| e.to_string())?.path();
+                    if !save::is_save_artifact(&entry_path) && formats.is_image(&entry_path) {
+                        files.push(entry_path);
+                    }
+                }
+            }
+        } else if path.is_file() {
+            let owned_path = path.clone();
+            if formats.is_image(&owned_path) {
+                files.push(owned_path);
+            }
+        }
+    }
+    Ok(files)
+}
+
+/// Check if a file would be listed from requested paths
+pub fn is_listed(path: &Path, paths: &[PathBuf], config: &Config, formats: &Formats) -> bool {
+    let requested = paths.iter().any(|p| p == path);
+    is_in_scope(path, paths, config) && (requested || !save::is_save_artifact(path)) && formats.is_image(path)
+}
+
+/// Check if a path is located where it would be listed from requested paths, whatever its type
+pub fn is_in_scope(path: &Path, paths: &[PathBuf], config: &Config) -> bool {
+    paths.iter().any(|root| {
+        if root == path {
+            return true;
+        }
+        let Ok(relative) = path.strip_prefix(root) else { return false };
+        if !(root.as_os_str().is_empty() || root.is_dir()) {
+            return false;
+        }
+        let depth = relative.components().count();
+        if config.recursive {
+            let hidden = relative.components().any(|c| is_hidden(Path::new(c.as_os_str())));
+            depth > 0 && depth <= config.max_depth + 1 && (config.show_hidden || !hidden)
+        } else {
+            depth == 1
+        }
+    })
+}
+
+/// Return the path to display for a file: relative to its requested root in recursive mode
+pub fn display_path(path: &Path, paths: &[PathBuf], config: &Config) -> String {
+    if config.recursive {
+        // use the deepest root, requested paths may be nested, or be the file itself
+        let relative = paths.iter()
+            .filter_map(|root| path.strip_prefix(root).ok())
+            .filter(|p| !p.as_os_str().is_empty())
+            .min_by_key(|p| p.components().count());
+        if let Some(relative) = relative && relative.as_os_str() != path.as_os_str() {
+            return relative.display().to_string();
+        }
+    }
+    path.display().to_string()
+}
+
+/// Recursively list image files of a directory
+///
+/// Directories already visited (e.g. through a symlink) are skipped.
+/// Errors on subdirectories are reported but not fatal.
+fn walk_dir(dir: &Path, config: &Config, formats: &Formats, depth: usize, visited: &mut HashSet<PathBuf>, files: &mut Vec<PathBuf>) -> Result<(), String> {
+    // an empty path is the current directory
+    let canonical = if dir.as_os_str().is_empty() { Path::new(".") } else { dir }.canonicalize();
+    if let Ok(canonical) = canonical && !visited.insert(canonical) {
+        return Ok(());  // symlink loop, or directory reached twice
+    }
+
+    let entries = fs::read_dir(dir).map_err(|e| format!("{}: {e}", dir.display()))?;
+    for entry in entries {
+        let entry_path = entry.map_err(|e| e.to_string())?.path();
+        if !config.show_hidden && is_hidden(&entry_path) {
+            continue;
+        }
+        if entry_path.is_dir() {
+            if depth < config.max_depth && let Err(e) = walk_dir(&entry_path, config, formats, depth + 1, visited, files) {
+                eprintln!("cannot read directory: {e}");
+            }
+        } else if !save::is_save_artifact(&entry_path) && formats.is_image(&entry_path) {
+            files.push(entry_path);
+        }
+    }
+    Ok(())
+}
+
+/// Check if a path is hidden (its name starts with a dot)
+fn is_hidden(path: &Path) -> bool {
+    path.file_name().is_some_and(|name| name.as_encoded_bytes().starts_with(b"."))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Create an empty temporary directory, unique to a test
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("riew-test-{}-{name}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn list_backups() {
+        // smallest data detected as JPEG: start and end of image
+        const JPEG: &[u8] = &[0xFF, 0xD8, 0xFF, 0xD9];
+        let dir = temp_dir("list-backups");
+        for name in ["photo.JPG~", "saved.jpg", "saved.jpg~", "saved.jpg.riew-tmp"] {
+            fs::write(dir.join(name), JPEG).unwrap();
+        }
+        let config = Config::default();
+        let formats = Formats::init(&config);
+        let mut files = list_files(std::slice::from_ref(&dir), &config, &formats).unwrap();
+        files.sort();
+        assert_eq!(files, [dir.join("photo.JPG~"), dir.join("saved.jpg")]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
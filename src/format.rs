@@ -0,0 +1,100 @@
+use std::collections::HashSet;
+use std::os::raw::c_int;
+use std::path::Path;
+use sdl2::image::ImageRWops;
+use sdl2::rwops::RWops;
+use sdl2::sys::SDL_RWops;
+use sdl2::sys::image::IMG_Init;
+use crate::config::Config;
+
+
+/// Image format readable by SDL_image
+struct Format {
+    /// Lowercase extensions, used when the content is not recognized
+    extensions: &'static [&'static str],
+    /// Check magic bytes, None for formats without magic
+    is: Option<fn(&RWops) -> bool>,
+    /// Flag of the dynamically loaded library required by the format, 0 if built-in
+    init_flag: c_int,
+}
+
+/// Detect image files, using formats supported by SDL_image
+pub struct Formats {
+    /// Formats supported by the linked SDL_image
+    formats: Vec<&'static Format>,
+    /// Accepted extensions (lowercase), including additional ones from configuration
+    extensions: HashSet<String>,
+    /// Extensions never accepted (lowercase)
+    excluded: HashSet<String>,
+}
+
+
+const IMG_INIT_JPG: c_int = 0x01;
+const IMG_INIT_PNG: c_int = 0x02;
+const IMG_INIT_TIF: c_int = 0x04;
+const IMG_INIT_WEBP: c_int = 0x08;
+const IMG_INIT_JXL: c_int = 0x10;
+const IMG_INIT_AVIF: c_int = 0x20;
+
+const FORMATS: [Format; 18] = [
+    Format { extensions: &["jpg", "jpeg", "jpe", "jfif"], is: Some(|r| r.is_jpg()), init_flag: IMG_INIT_JPG },
+    Format { extensions: &["png"], is: Some(|r| r.is_png()), init_flag: IMG_INIT_PNG },
+    Format { extensions: &["tif", "tiff"], is: Some(|r| r.is_tif()), init_flag: IMG_INIT_TIF },
+    Format { extensions: &["webp"], is: Some(|r| r.is_webp()), init_flag: IMG_INIT_WEBP },
+    Format { extensions: &["jxl"], is: Some(|r| unsafe { IMG_isJXL(r.raw()) } != 0), init_flag: IMG_INIT_JXL },
+    Format { extensions: &["avif"], is: Some(|r| unsafe { IMG_isAVIF(r.raw()) } != 0), init_flag: IMG_INIT_AVIF },
+    Format { extensions: &["gif"], is: Some(|r| r.is_gif()), init_flag: 0 },
+    Format { extensions: &["bmp", "dib"], is: Some(|r| r.is_bmp()), init_flag: 0 },
+    Format { extensions: &["pnm", "pbm", "pgm", "ppm"], is: Some(|r| r.is_pnm()), init_flag: 0 },
+    Format { extensions: &["xpm"], is: Some(|r| r.is_xpm()), init_flag: 0 },
+    Format { extensions: &["xcf"], is: Some(|r| r.is_xcf()), init_flag: 0 },
+    Format { extensions: &["pcx"], is: Some(|r| r.is_pcx()), init_flag: 0 },
+    Format { extensions: &["lbm", "iff"], is: Some(|r| r.is_lbm()), init_flag: 0 },
+    Format { extensions: &["ico"], is: Some(|r| r.is_ico()), init_flag: 0 },
+    Format { extensions: &["cur"], is: Some(|r| r.is_cur()), init_flag: 0 },
+    Format { extensions: &["qoi"], is: Some(|r| unsafe { IMG_isQOI(r.raw()) } != 0), init_flag: 0 },
+    Format { extensions: &["svg"], is: Some(|r| unsafe { IMG_isSVG(r.raw()) } != 0), init_flag: 0 },
+    Format { extensions: &["tga"], is: None, init_flag: 0 },
+];
+
+
+impl Formats {
+    /// Initialize SDL_image loaders, get supported formats
+    pub fn init(config: &Config) -> Self {
+        let all_flags = FORMATS.iter().fold(0, |flags, f| flags | f.init_flag);
+        // returns loaders that are available, others are simply not initialized
+        let init_flags = unsafe { IMG_Init(all_flags) };
+        let formats: Vec<_> = FORMATS.iter()
+            .filter(|f| f.init_flag & init_flags == f.init_flag)
+            .collect();
+
+        let lowercase = |exts: &[String]| exts.iter().map(|e| e.trim_start_matches('.').to_lowercase()).collect::<HashSet<_>>();
+        let mut extensions = lowercase(&config.extensions);
+        extensions.extend(formats.iter().flat_map(|f| f.extensions).map(|e| e.to_string()));
+        let excluded = lowercase(&config.excluded_extensions);
+
+        Self { formats, extensions, excluded }
+    }
+
+    /// Check if a file is an image, from its content or its extension
+    pub fn is_image(&self, path: &Path) -> bool {
+        let ext = path.extension().map(|e| e.to_string_lossy().to_lowercase());
+        if ext.as_ref().is_some_and(|ext| self.excluded.contains(ext)) || !path.is_file() {
+            return false;
+        }
+        let Ok(rwops) = RWops::from_file(path, "rb") else { return false };
+        if self.formats.iter().filter_map(|f| f.is).any(|is| is(&rwops)) {
+            return true;
+        }
+        ext.is_some_and(|ext| self.extensions.contains(&ext))
+    }
+}
+
+
+// Detection functions of SDL_image 2.6, not provided by `sdl2`
+unsafe extern "C" {
+    fn IMG_isAVIF(src: *mut SDL_RWops) -> c_int;
+    fn IMG_isJXL(src: *mut SDL_RWops) -> c_int;
+    fn IMG_isQOI(src: *mut SDL_RWops) -> c_int;
+    fn IMG_isSVG(src: *mut SDL_RWops) -> c_int;
+}
@@ -0,0 +1,248 @@
+use std::collections::{HashMap, HashSet};
+use std::ops::Range;
+use std::path::PathBuf;
+use sdl2::rect::Rect;
+use crate::display::Image;
+
+
+/// Grid of thumbnails, to browse files
+///
+/// Cells are laid out left to right, then top to bottom, in file list order.
+pub struct Gallery {
+    /// Index of the selected file
+    pub selected: usize,
+    /// Vertical scroll offset, in pixels
+    scroll: i32,
+    /// Size of a cell, thumbnail and padding, in pixels
+    cell_size: u32,
+    /// Thumbnails, None if the thumbnail could not be created
+    pub thumbnails: HashMap<PathBuf, Option<Image>>,
+    /// Set if the animation of the current image was paused when the gallery was opened
+    pub animation_paused: bool,
+}
+
+impl Gallery {
+    /// Space around thumbnails
+    const PADDING: u32 = 8;
+    /// Space above the grid, for the file information
+    const TOP_MARGIN: i32 = 30;
+    /// Maximum number of thumbnails kept in memory
+    const MAX_THUMBNAILS: usize = 1000;
+
+    pub fn new(selected: usize, thumbnail_size: u32) -> Self {
+        Self {
+            selected,
+            scroll: 0,
+            cell_size: thumbnail_size + 2 * Self::PADDING,
+            thumbnails: HashMap::new(),
+            animation_paused: false,
+        }
+    }
+
+    /// Return the number of columns
+    pub fn columns(&self, output_size: (u32, u32)) -> usize {
+        (output_size.0 / self.cell_size).max(1) as usize
+    }
+
+    /// Return the number of fully visible rows, at least one
+    pub fn page_rows(&self, output_size: (u32, u32)) -> usize {
+        let height = output_size.1.saturating_sub(Self::TOP_MARGIN as u32);
+        (height / self.cell_size).max(1) as usize
+    }
+
+    /// Return the rectangle of a cell, on screen
+    pub fn cell_rect(&self, index: usize, output_size: (u32, u32)) -> Rect {
+        let columns = self.columns(output_size);
+        let (col, row) = ((index % columns) as i32, (index / columns) as i32);
+        let cell = self.cell_size as i32;
+        // center the grid horizontally
+        let left = (output_size.0 as i32 - columns as i32 * cell).max(0) / 2;
+        Rect::new(left + col * cell, Self::TOP_MARGIN + row * cell - self.scroll, self.cell_size, self.cell_size)
+    }
+
+    /// Return the rectangle of a thumbnail in a cell, on screen
+    pub fn thumbnail_rect(&self, index: usize, output_size: (u32, u32)) -> Rect {
+        let rect = self.cell_rect(index, output_size);
+        let size = self.cell_size - 2 * Self::PADDING;
+        Rect::new(rect.x() + Self::PADDING as i32, rect.y() + Self::PADDING as i32, size, size)
+    }
+
+    /// Return the index of the cell at a screen position
+    pub fn index_at(&self, pos: (i32, i32), output_size: (u32, u32), count: usize) -> Option<usize> {
+        let cell = self.cell_size as i32;
+        let first = self.cell_rect(0, output_size);
+        let (x, y) = (pos.0 - first.x(), pos.1 - first.y());
+        let columns = self.columns(output_size);
+        if x < 0 || y < 0 || x / cell >= columns as i32 {
+            return None;
+        }
+        let index = (y / cell) as usize * columns + (x / cell) as usize;
+        Some(index).filter(|i| *i < count)
+    }
+
+    /// Return the range of indexes of visible cells, even partially
+    pub fn visible_range(&self, output_size: (u32, u32), count: usize) -> Range<usize> {
+        let columns = self.columns(output_size);
+        let cell = self.cell_size as i32;
+        let first_row = ((self.scroll - Self::TOP_MARGIN).max(0) / cell) as usize;
+        // last visible pixel row, a cell starting at the bottom edge is not visible
+        let last_row = ((self.scroll + output_size.1 as i32 - Self::TOP_MARGIN - 1).max(0) / cell) as usize;
+        (first_row * columns).min(count)..((last_row + 1) * columns).min(count)
+    }
+
+    /// Move the selection, relatively, stop at the first and last files
+    pub fn move_selection(&mut self, offset: isize, count: usize, output_size: (u32, u32)) {
+        if count == 0 {
+            return;
+        }
+        self.selected = self.selected.saturating_add_signed(offset).min(count - 1);
+        self.scroll_to_selected(output_size);
+    }
+
+    /// Scroll so that the selected cell is fully visible
+    pub fn scroll_to_selected(&mut self, output_size: (u32, u32)) {
+        let row = (self.selected / self.columns(output_size)) as i32;
+        let cell = self.cell_size as i32;
+        let top = row * cell;
+        let bottom = top + cell - (output_size.1 as i32 - Self::TOP_MARGIN);
+        self.scroll = self.scroll.clamp(bottom.min(top), top);
+    }
+
+    /// Scroll by given number of pixels, stop at the first and last rows
+    pub fn scroll_by(&mut self, offset: i32, count: usize, output_size: (u32, u32)) {
+        let rows = count.div_ceil(self.columns(output_size)) as i32;
+        let max_scroll = (rows * self.cell_size as i32 - (output_size.1 as i32 - Self::TOP_MARGIN)).max(0);
+        self.scroll = (self.scroll + offset).clamp(0, max_scroll);
+    }
+
+    /// Drop thumbnails far from the visible ones, if there are too many
+    pub fn evict(&mut self, files: &[PathBuf], output_size: (u32, u32)) {
+        if self.thumbnails.len() <= Self::MAX_THUMBNAILS {
+            return;
+        }
+        let visible = self.visible_range(output_size, files.len());
+        let margin = Self::MAX_THUMBNAILS / 4;
+        let keep: HashSet<_> = files[visible.start.saturating_sub(margin)..(visible.end + margin).min(files.len())].iter().collect();
+        self.thumbnails.retain(|path, _| keep.contains(path));
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Window of 3 columns of 100-pixel cells, with 30 pixels on each side
+    const OUTPUT_SIZE: (u32, u32) = (360, 430);
+
+    fn gallery() -> Gallery {
+        Gallery::new(0, 100 - 2 * Gallery::PADDING)
+    }
+
+    #[test]
+    fn layout() {
+        let g = gallery();
+        assert_eq!(g.columns(OUTPUT_SIZE), 3);
+        assert_eq!(g.columns((50, 50)), 1);
+        assert_eq!(g.page_rows(OUTPUT_SIZE), 4);
+        assert_eq!(g.page_rows((360, 429)), 3);
+        assert_eq!(g.page_rows((360, 10)), 1);
+        assert_eq!(g.cell_rect(0, OUTPUT_SIZE), Rect::new(30, 30, 100, 100));
+        assert_eq!(g.cell_rect(4, OUTPUT_SIZE), Rect::new(130, 130, 100, 100));
+        assert_eq!(g.thumbnail_rect(4, OUTPUT_SIZE), Rect::new(138, 138, 84, 84));
+    }
+
+    #[test]
+    fn index_at() {
+        let mut g = gallery();
+        assert_eq!(g.index_at((30, 30), OUTPUT_SIZE, 10), Some(0));
+        assert_eq!(g.index_at((129, 129), OUTPUT_SIZE, 10), Some(0));
+        assert_eq!(g.index_at((130, 130), OUTPUT_SIZE, 10), Some(4));
+        assert_eq!(g.index_at((329, 30), OUTPUT_SIZE, 10), Some(2));
+        // margins, missing cells
+        assert_eq!(g.index_at((29, 30), OUTPUT_SIZE, 10), None);
+        assert_eq!(g.index_at((330, 30), OUTPUT_SIZE, 10), None);
+        assert_eq!(g.index_at((30, 29), OUTPUT_SIZE, 10), None);
+        assert_eq!(g.index_at((130, 330), OUTPUT_SIZE, 10), None);
+        g.scroll = 150;
+        assert_eq!(g.index_at((30, 0), OUTPUT_SIZE, 10), Some(3));
+        assert_eq!(g.index_at((30, 30), OUTPUT_SIZE, 10), Some(3));
+        assert_eq!(g.index_at((30, 80), OUTPUT_SIZE, 10), Some(6));
+    }
+
+    #[test]
+    fn visible_range() {
+        let mut g = gallery();
+        // 4 rows exactly fill the height below the top margin
+        assert_eq!(g.visible_range(OUTPUT_SIZE, 100), 0..12);
+        assert_eq!(g.visible_range(OUTPUT_SIZE, 5), 0..5);
+        assert_eq!(g.visible_range((360, 431), 100), 0..15);
+        // partially visible rows
+        g.scroll = 129;
+        assert_eq!(g.visible_range(OUTPUT_SIZE, 100), 0..18);
+        g.scroll = 130;
+        assert_eq!(g.visible_range(OUTPUT_SIZE, 100), 3..18);
+        // sixth row starts at the bottom edge
+        g.scroll = 100;
+        assert_eq!(g.visible_range(OUTPUT_SIZE, 100), 0..15);
+        assert_eq!(g.visible_range(OUTPUT_SIZE, 0), 0..0);
+    }
+
+    #[test]
+    fn scroll() {
+        let mut g = gallery();
+        g.selected = 13;  // fifth row
+        g.scroll_to_selected(OUTPUT_SIZE);
+        assert_eq!(g.scroll, 100);
+        g.selected = 4;
+        g.scroll_to_selected(OUTPUT_SIZE);
+        assert_eq!(g.scroll, 100);
+        g.selected = 1;
+        g.scroll_to_selected(OUTPUT_SIZE);
+        assert_eq!(g.scroll, 0);
+        // cells higher than the window: top of the cell is visible
+        g.selected = 13;
+        g.scroll_to_selected((360, 80));
+        assert_eq!(g.scroll, 400);
+
+        let mut g = gallery();
+        g.scroll_by(-10, 20, OUTPUT_SIZE);
+        assert_eq!(g.scroll, 0);
+        g.scroll_by(150, 20, OUTPUT_SIZE);
+        assert_eq!(g.scroll, 150);
+        // 7 rows
+        g.scroll_by(1000, 20, OUTPUT_SIZE);
+        assert_eq!(g.scroll, 300);
+        g.scroll_by(1000, 5, OUTPUT_SIZE);
+        assert_eq!(g.scroll, 0);
+    }
+
+    #[test]
+    fn move_selection() {
+        let mut g = gallery();
+        g.move_selection(-1, 20, OUTPUT_SIZE);
+        assert_eq!(g.selected, 0);
+        g.move_selection(15, 20, OUTPUT_SIZE);
+        assert_eq!((g.selected, g.scroll), (15, 200));
+        g.move_selection(10, 20, OUTPUT_SIZE);
+        assert_eq!((g.selected, g.scroll), (19, 300));
+        g.move_selection(1, 0, OUTPUT_SIZE);
+        assert_eq!(g.selected, 19);
+    }
+
+    #[test]
+    fn evict() {
+        let files: Vec<PathBuf> = (0..2000).map(|i| PathBuf::from(format!("{i}.png"))).collect();
+        let mut g = gallery();
+        g.thumbnails = files[..Gallery::MAX_THUMBNAILS].iter().map(|p| (p.clone(), None)).collect();
+        g.evict(&files, OUTPUT_SIZE);
+        assert_eq!(g.thumbnails.len(), Gallery::MAX_THUMBNAILS);
+
+        g.thumbnails = files.iter().map(|p| (p.clone(), None)).collect();
+        g.scroll = 30 + 100 * 300;  // first visible cell: 900
+        g.evict(&files, OUTPUT_SIZE);
+        let mut kept: Vec<usize> = g.thumbnails.keys().map(|p| files.iter().position(|f| f == p).unwrap()).collect();
+        kept.sort_unstable();
+        assert_eq!(kept, (650..1165).collect::<Vec<_>>());
+    }
+}
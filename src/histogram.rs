@@ -0,0 +1,107 @@
+/// Distribution of channel values of an image
+///
+/// Fully transparent pixels are ignored, their color is not displayed.
+pub struct Histogram {
+    /// Number of pixels of each value, for red, green, blue and luminance
+    pub channels: [[u32; 256]; 4],
+    /// Number of counted pixels
+    pub pixel_count: u32,
+}
+
+/// Channels of a histogram, in the order of `Histogram::channels`
+pub const CHANNEL_NAMES: [&str; 4] = ["R", "G", "B", "L"];
+
+impl Histogram {
+    /// Compute the histogram of `RGBA32` pixels
+    pub fn new(pixels: &[u8]) -> Self {
+        let mut channels = [[0u32; 256]; 4];
+        let mut pixel_count = 0;
+        for p in pixels.chunks_exact(4).filter(|p| p[3] != 0) {
+            let (r, g, b) = (p[0] as u32, p[1] as u32, p[2] as u32);
+            // Rec. 709 luma, with integer weights summing to 256
+            let luminance = (54 * r + 183 * g + 19 * b + 128) >> 8;
+            for (channel, value) in channels.iter_mut().zip([r, g, b, luminance]) {
+                channel[value as usize] += 1;
+            }
+            pixel_count += 1;
+        }
+        Self { channels, pixel_count }
+    }
+
+    /// Return the mean value of a channel
+    pub fn mean(&self, channel: usize) -> f32 {
+        let sum: u64 = self.channels[channel].iter().enumerate().map(|(v, n)| v as u64 * *n as u64).sum();
+        (sum as f64 / self.pixel_count.max(1) as f64) as f32
+    }
+
+    /// Return the median value of a channel
+    pub fn median(&self, channel: usize) -> u8 {
+        let half = self.pixel_count.div_ceil(2);
+        let mut count = 0;
+        for (value, n) in self.channels[channel].iter().enumerate() {
+            count += n;
+            if count >= half && count > 0 {
+                return value as u8;
+            }
+        }
+        0
+    }
+
+    /// Return the ratios of pixels clipped to pure black (0) and pure white (255), in a channel
+    pub fn clipping(&self, channel: usize) -> (f32, f32) {
+        let total = self.pixel_count.max(1) as f32;
+        let values = &self.channels[channel];
+        (values[0] as f32 / total, values[255] as f32 / total)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn histogram(pixels: &[[u8; 4]]) -> Histogram {
+        Histogram::new(&pixels.concat())
+    }
+
+    #[test]
+    fn transparent() {
+        let h = histogram(&[[255, 0, 0, 0], [0, 255, 0, 0]]);
+        assert_eq!(h.pixel_count, 0);
+        for channel in 0..4 {
+            assert_eq!(h.mean(channel), 0.);
+            assert_eq!(h.median(channel), 0);
+            assert_eq!(h.clipping(channel), (0., 0.));
+        }
+    }
+
+    #[test]
+    fn luminance() {
+        let h = histogram(&[[255, 0, 0, 255], [0, 255, 0, 255], [0, 0, 255, 255], [255, 255, 255, 255]]);
+        assert_eq!(h.pixel_count, 4);
+        let luminances: Vec<usize> = (0..256).filter(|&v| h.channels[3][v] > 0).collect();
+        assert_eq!(luminances, [19, 54, 182, 255]);
+    }
+
+    #[test]
+    fn mean_median() {
+        // partially transparent pixels are counted
+        let h = histogram(&[[10, 0, 0, 255], [20, 0, 0, 1], [30, 0, 0, 255], [100, 0, 0, 255], [0, 0, 0, 0]]);
+        assert_eq!(h.pixel_count, 4);
+        assert_eq!(h.mean(0), 40.);
+        // lower median for an even count
+        assert_eq!(h.median(0), 20);
+        let h = histogram(&[[10, 0, 0, 255], [20, 0, 0, 255], [30, 0, 0, 255]]);
+        assert_eq!(h.median(0), 20);
+    }
+
+    #[test]
+    fn clipping() {
+        let h = histogram(&[[0, 255, 0, 255], [0, 255, 128, 255]]);
+        assert_eq!(h.clipping(0), (1., 0.));
+        assert_eq!(h.clipping(1), (0., 1.));
+        assert_eq!(h.clipping(2), (0.5, 0.));
+        assert_eq!((h.mean(1), h.median(1)), (255., 255));
+        assert_eq!((h.mean(0), h.median(0)), (0., 0));
+    }
+}
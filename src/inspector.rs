@@ -0,0 +1,246 @@
+use sdl2::pixels::Color;
+use serde::Deserialize;
+use crate::decode::Pixel;
+
+
+/// Maximum size of the squares of pixels around the picked one
+pub const MAX_SQUARE_SIZE: u32 = 63;
+
+/// Additional information displayed by the pixel inspector
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum InspectorField {
+    /// Alpha value, next to color values
+    Alpha,
+    /// Hue, saturation, value
+    Hsv,
+    /// Hue, saturation, lightness
+    Hsl,
+    /// CIE L*a*b*, D65 white point
+    Lab,
+    /// Channel values normalized to `[0, 1]`
+    Float,
+    /// Average color of the pixels around the picked one
+    Average,
+}
+
+/// Picked pixel, with its surroundings
+pub struct PixelInfo {
+    /// Position in the image
+    pub pos: (i32, i32),
+    pub pixel: Pixel,
+    /// Average color of the pixels around, in a square of `average_size` pixels
+    pub average: Color,
+    pub average_size: u32,
+    /// Pixels around, row by row, in a square of `loupe_size` pixels (None outside the image)
+    pub loupe: Vec<Option<Color>>,
+    pub loupe_size: u32,
+}
+
+impl PixelInfo {
+    /// Pick a pixel of an image, given by its pixel lookup function, None if outside the image
+    ///
+    /// Square sizes are rounded up to odd numbers, to be centered on the pixel.
+    pub fn new(pixel_at: impl Fn((i32, i32)) -> Option<Pixel>, pos: (i32, i32), average_size: u32, loupe_size: u32) -> Option<Self> {
+        let pixel = pixel_at(pos)?;
+        let average_size = average_size.max(1) | 1;
+        let (loupe, loupe_size) = match loupe_size {
+            0 => (Vec::new(), 0),
+            size => (square(&pixel_at, pos, size | 1).collect(), size | 1),
+        };
+        Some(Self {
+            pos,
+            pixel,
+            average: average_color(square(&pixel_at, pos, average_size).flatten()),
+            average_size,
+            loupe,
+            loupe_size,
+        })
+    }
+
+    /// Return true if alpha is displayed, for images with an alpha channel
+    pub fn shows_alpha(&self, fields: &[InspectorField]) -> bool {
+        self.pixel.channel_bits[3] > 0 && fields.contains(&InspectorField::Alpha)
+    }
+
+    /// Return the hexadecimal code of the color, with alpha if displayed
+    pub fn hex(&self, fields: &[InspectorField]) -> String {
+        let Color { r, g, b, a } = self.pixel.color;
+        if self.shows_alpha(fields) {
+            format!("#{r:02X}{g:02X}{b:02X}{a:02X}")
+        } else {
+            format!("#{r:02X}{g:02X}{b:02X}")
+        }
+    }
+
+    /// Return the text of a field, None if displayed elsewhere
+    pub fn field_text(&self, field: InspectorField) -> Option<String> {
+        let color = self.pixel.color;
+        let text = match field {
+            InspectorField::Alpha => return None,
+            InspectorField::Hsv => {
+                let (h, s, v) = hsv(color);
+                format!("HSV  {h:.0}°  {:.1}%  {:.1}%", s * 100., v * 100.)
+            },
+            InspectorField::Hsl => {
+                let (h, s, l) = hsl(color);
+                format!("HSL  {h:.0}°  {:.1}%  {:.1}%", s * 100., l * 100.)
+            },
+            InspectorField::Lab => {
+                let (l, a, b) = lab(color);
+                format!("Lab  {l:.1}  {a:.1}  {b:.1}")
+            },
+            InspectorField::Float => {
+                let Color { r, g, b, a } = color;
+                let [r, g, b, a] = [r, g, b, a].map(|c| c as f32 / 255.);
+                format!("float  {r:.3}  {g:.3}  {b:.3}  {a:.3}")
+            },
+            InspectorField::Average => {
+                let Color { r, g, b, a } = self.average;
+                let size = self.average_size;
+                format!("average {size} × {size}  #{r:02X}{g:02X}{b:02X}{a:02X}  {r} {g} {b} {a}")
+            },
+        };
+        Some(text)
+    }
+}
+
+
+/// Return the colors of a square of pixels centered on a position, row by row
+fn square(pixel_at: &impl Fn((i32, i32)) -> Option<Pixel>, (x, y): (i32, i32), size: u32) -> impl Iterator<Item = Option<Color>> {
+    let radius = size as i32 / 2;
+    (y - radius..=y + radius)
+        .flat_map(move |py| (x - radius..=x + radius).map(move |px| (px, py)))
+        .map(|pos| pixel_at(pos).map(|p| p.color))
+}
+
+/// Return the average of colors, channel by channel
+fn average_color(colors: impl Iterator<Item = Color>) -> Color {
+    let mut sum = [0u32; 4];
+    let mut count = 0;
+    for c in colors {
+        for (s, v) in sum.iter_mut().zip([c.r, c.g, c.b, c.a]) {
+            *s += v as u32;
+        }
+        count += 1;
+    }
+    let [r, g, b, a] = sum.map(|s| ((s + count / 2) / count.max(1)) as u8);
+    Color::RGBA(r, g, b, a)
+}
+
+/// Return the hue (in degrees), the chroma and the min and max of normalized channels
+fn hue_chroma(color: Color) -> (f32, f32, f32, f32) {
+    let [r, g, b] = [color.r, color.g, color.b].map(|c| c as f32 / 255.);
+    let max = r.max(g).max(b);
+    let min = r.min(g).min(b);
+    let chroma = max - min;
+    let hue = if chroma == 0. {
+        0.
+    } else if max == r {
+        60. * ((g - b) / chroma).rem_euclid(6.)
+    } else if max == g {
+        60. * ((b - r) / chroma + 2.)
+    } else {
+        60. * ((r - g) / chroma + 4.)
+    };
+    (hue, chroma, min, max)
+}
+
+/// Convert a color to hue (in degrees), saturation and value
+pub fn hsv(color: Color) -> (f32, f32, f32) {
+    let (hue, chroma, _, max) = hue_chroma(color);
+    let saturation = if max == 0. { 0. } else { chroma / max };
+    (hue, saturation, max)
+}
+
+/// Convert a color to hue (in degrees), saturation and lightness
+pub fn hsl(color: Color) -> (f32, f32, f32) {
+    let (hue, chroma, min, max) = hue_chroma(color);
+    let lightness = (max + min) / 2.;
+    let saturation = if chroma == 0. { 0. } else { chroma / (1. - (2. * lightness - 1.).abs()) };
+    (hue, saturation, lightness)
+}
+
+/// Convert an sRGB color to CIE L*a*b*, with a D65 white point
+pub fn lab(color: Color) -> (f32, f32, f32) {
+    let linear = |c: u8| {
+        let c = c as f32 / 255.;
+        if c <= 0.04045 { c / 12.92 } else { ((c + 0.055) / 1.055).powf(2.4) }
+    };
+    let (r, g, b) = (linear(color.r), linear(color.g), linear(color.b));
+    let x = (0.412_456_4 * r + 0.357_576_1 * g + 0.180_437_5 * b) / 0.950_47;
+    let y = 0.212_672_9 * r + 0.715_152_2 * g + 0.072_175 * b;
+    let z = (0.019_333_9 * r + 0.119_192 * g + 0.950_304_1 * b) / 1.088_83;
+
+    const DELTA: f32 = 6. / 29.;
+    let f = |t: f32| if t > DELTA.powi(3) { t.cbrt() } else { t / (3. * DELTA * DELTA) + 4. / 29. };
+    let (fx, fy, fz) = (f(x), f(y), f(z));
+    (116. * fy - 16., 500. * (fx - fy), 200. * (fy - fz))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decode::{DecodedImage, Frame};
+
+    fn assert_near(a: (f32, f32, f32), b: (f32, f32, f32)) {
+        let near = |x: f32, y: f32| (x - y).abs() < 0.01;
+        assert!(near(a.0, b.0) && near(a.1, b.1) && near(a.2, b.2), "{a:?} != {b:?}");
+    }
+
+    #[test]
+    fn hsv_hsl() {
+        let primaries = [(Color::RGB(255, 0, 0), 0.), (Color::RGB(0, 255, 0), 120.), (Color::RGB(0, 0, 255), 240.)];
+        for (color, hue) in primaries {
+            assert_near(hsv(color), (hue, 1., 1.));
+            assert_near(hsl(color), (hue, 1., 0.5));
+        }
+        assert_near(hsv(Color::RGB(255, 0, 255)), (300., 1., 1.));
+        assert_near(hsv(Color::RGB(128, 128, 128)), (0., 0., 128. / 255.));
+        assert_near(hsl(Color::RGB(128, 128, 128)), (0., 0., 128. / 255.));
+        assert_near(hsv(Color::RGB(0, 0, 0)), (0., 0., 0.));
+        assert_near(hsl(Color::RGB(255, 255, 255)), (0., 0., 1.));
+    }
+
+    #[test]
+    fn lab_reference() {
+        assert_near(lab(Color::RGB(255, 255, 255)), (100., 0., 0.));
+        assert_near(lab(Color::RGB(0, 0, 0)), (0., 0., 0.));
+        assert_near(lab(Color::RGB(255, 0, 0)), (53.24, 80.09, 67.20));
+        assert_near(lab(Color::RGB(0, 255, 0)), (87.73, -86.18, 83.18));
+        assert_near(lab(Color::RGB(0, 0, 255)), (32.30, 79.19, -107.86));
+        let (_, a, b) = lab(Color::RGB(119, 119, 119));
+        assert_near((0., a, b), (0., 0., 0.));
+    }
+
+    #[test]
+    fn square_sizes() {
+        // pixels of increasing red values, row by row
+        let decoded = DecodedImage {
+            width: 5,
+            height: 5,
+            frames: vec![Frame { pixels: (0..25).flat_map(|i| [i * 10, 0, 0, 255]).collect(), delay: 0 }],
+            orientation: None,
+            levels: Vec::new(),
+            channel_bits: DecodedImage::FULL_DEPTH,
+        };
+        let pixel_at = |pos| decoded.pixel(0, pos);
+        assert!(PixelInfo::new(pixel_at, (5, 0), 3, 3).is_none());
+
+        let info = PixelInfo::new(pixel_at, (2, 2), 2, 4).unwrap();
+        assert_eq!((info.average_size, info.loupe_size), (3, 5));
+        assert_eq!(info.loupe.len(), 25);
+        assert_eq!(info.pixel.color, Color::RGB(120, 0, 0));
+        assert_eq!(info.average, Color::RGB(120, 0, 0));
+
+        // pixels outside the image are ignored by the average, missing from the loupe
+        let info = PixelInfo::new(pixel_at, (0, 0), 0, 0).unwrap();
+        assert_eq!((info.average_size, info.loupe_size, info.loupe.len()), (1, 0, 0));
+        let info = PixelInfo::new(pixel_at, (0, 0), 3, 1).unwrap();
+        assert_eq!(info.average, Color::RGB(30, 0, 0));
+        assert_eq!(info.loupe, [Some(Color::RGB(0, 0, 0))]);
+        let info = PixelInfo::new(pixel_at, (0, 0), 1, 3).unwrap();
+        assert_eq!(info.loupe.iter().filter(|c| c.is_none()).count(), 5);
+    }
+}
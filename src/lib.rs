@@ -1,8 +1,28 @@
 #[macro_use]
 mod macros;
 mod app;
+mod bindings;
+mod clipboard;
+mod config;
+mod decode;
+mod diff;
 mod display;
+mod exif;
+mod filelist;
+mod format;
+mod gallery;
+mod histogram;
+mod inspector;
+mod prefetch;
+mod save;
+mod sort;
+mod thumbnail;
+mod view;
+mod watch;
 
 pub use display::Display;
 pub use app::App;
+pub use config::{Config, parse_color};
+pub use bindings::Bindings;
+pub use sort::SortKey;
 
@@ -13,11 +13,99 @@ struct Cli {
     /// browse given files
     #[arg(value_name = "FILE", group = "input")]
     files: Option<Vec<PathBuf>>,
+    /// configuration file (default: riew.toml in user's configuration directory)
+    #[arg(short, long, value_name = "FILE")]
+    config: Option<PathBuf>,
+    /// number of files to preload before and after the current one
+    #[arg(long, value_name = "N")]
+    prefetch: Option<usize>,
+    /// memory budget of preloaded images, in MiB
+    #[arg(long, value_name = "MIB")]
+    cache_size: Option<usize>,
+    /// browse directories recursively
+    #[arg(short, long)]
+    recursive: bool,
+    /// maximum depth of subdirectories, in recursive mode
+    #[arg(long, value_name = "N")]
+    max_depth: Option<usize>,
+    /// include hidden files and directories, in recursive mode
+    #[arg(long)]
+    hidden: bool,
+    /// don't watch files for changes
+    #[arg(long, conflicts_with = "follow")]
+    no_watch: bool,
+    /// display new files as soon as they are created
+    #[arg(long)]
+    follow: bool,
+    /// order of the file list
+    #[arg(long, value_name = "KEY")]
+    sort: Option<riew::SortKey>,
+    /// reverse the order of the file list
+    #[arg(long)]
+    reverse: bool,
+    /// background color (#RRGGBB), or `checkerboard`
+    #[arg(long, value_name = "COLOR")]
+    background: Option<String>,
+    /// don't rotate images according to their EXIF orientation
+    #[arg(long)]
+    no_exif_rotate: bool,
+    /// print active key bindings, in configuration format, and exit
+    #[arg(long)]
+    dump_bindings: bool,
 }
 
 fn main() -> Result<(), String> {
     let cli = Cli::parse();
 
+    let mut config = match (cli.config, riew::Config::default_path()) {
+        (Some(path), _) => riew::Config::load(&path, false)?,
+        (None, Some(path)) => riew::Config::load(&path, true)?,
+        (None, None) => riew::Config::default(),
+    };
+    if let Some(count) = cli.prefetch {
+        config.prefetch_count = count;
+    }
+    if let Some(size) = cli.cache_size {
+        config.cache_size = size;
+    }
+    if cli.recursive {
+        config.recursive = true;
+    }
+    if let Some(depth) = cli.max_depth {
+        config.max_depth = depth;
+    }
+    if cli.hidden {
+        config.show_hidden = true;
+    }
+    if cli.no_watch {
+        config.watch = false;
+    }
+    if cli.follow {
+        config.follow = true;
+    }
+    if let Some(key) = cli.sort {
+        config.sort = key;
+    }
+    if cli.reverse {
+        config.sort_reverse = true;
+    }
+    if let Some(background) = cli.background {
+        if background == "checkerboard" {
+            config.checkerboard = true;
+        } else {
+            config.colors.background = riew::parse_color(&background)
+                .ok_or_else(|| format!("invalid background `{background}`, expected `#RRGGBB` or `checkerboard`"))?;
+            config.checkerboard = false;
+        }
+    }
+    if cli.no_exif_rotate {
+        config.exif_rotate = false;
+    }
+    if cli.dump_bindings {
+        print!("{}", riew::Bindings::new(&config.bindings));
+        return Ok(());
+    }
+
     let paths: Vec<_> =
         if let Some(file) = cli.directory {
             if let Some(parent) = file.parent() {
@@ -31,7 +119,7 @@ fn main() -> Result<(), String> {
         } else {
             vec![PathBuf::from("")]
         };
-    riew::App::init(paths)?.run()?;
+    riew::App::init(paths, config)?.run()?;
 
     Ok(())
 }
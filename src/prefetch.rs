@@ -0,0 +1,252 @@
+use std::collections::{HashMap, VecDeque};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Condvar, Mutex};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+use crate::decode::DecodedImage;
+
+
+type DecodeResult = Result<Arc<DecodedImage>, String>;
+
+/// Decode images in background threads, keep them in a cache
+///
+/// Images are decoded around the current file, nearest ones first.
+/// When the cache exceeds its memory budget, images the farthest from the current file are evicted.
+pub struct Prefetcher {
+    shared: Arc<Shared>,
+    results: Receiver<(PathBuf, DecodeResult)>,
+    cache: HashMap<PathBuf, Entry>,
+    /// Number of files to decode before and after the current one
+    count: usize,
+    /// Memory budget of decoded images, in bytes
+    budget: usize,
+}
+
+/// Cache entry
+enum Entry {
+    /// Decode has been requested
+    Pending,
+    /// Decode is complete
+    Done(DecodeResult),
+}
+
+/// Data shared with worker threads
+struct Shared {
+    queue: Mutex<Queue>,
+    condvar: Condvar,
+}
+
+/// Files waiting to be decoded, nearest first
+struct Queue {
+    paths: VecDeque<PathBuf>,
+    quit: bool,
+}
+
+
+impl Prefetcher {
+    const MAX_WORKERS: usize = 4;
+
+    /// Create the prefetcher and start its worker threads
+    pub fn new(count: usize, budget: usize) -> Self {
+        let shared = Arc::new(Shared {
+            queue: Mutex::new(Queue { paths: VecDeque::new(), quit: false }),
+            condvar: Condvar::new(),
+        });
+        let (sender, results) = mpsc::channel();
+
+        let nworkers = thread::available_parallelism()
+            .map_or(1, |n| n.get())
+            .min(Self::MAX_WORKERS)
+            .min(2 * count);
+        for _ in 0..nworkers {
+            let shared = shared.clone();
+            let sender = sender.clone();
+            thread::spawn(move || Self::worker(&shared, &sender));
+        }
+
+        Self {
+            shared,
+            results,
+            cache: HashMap::new(),
+            count,
+            budget,
+        }
+    }
+
+    /// Worker thread: decode queued files until asked to quit
+    fn worker(shared: &Shared, sender: &Sender<(PathBuf, DecodeResult)>) {
+        loop {
+            let path = {
+                let mut queue = shared.queue.lock().unwrap();
+                loop {
+                    if queue.quit {
+                        return;
+                    }
+                    if let Some(path) = queue.paths.pop_front() {
+                        break path;
+                    }
+                    queue = shared.condvar.wait(queue).unwrap();
+                }
+            };
+            let result = decode(&path);
+            if sender.send((path, result)).is_err() {
+                return;  // prefetcher dropped
+            }
+        }
+    }
+
+    /// Get a decoded image, decode it if needed
+    ///
+    /// If the image is being decoded by a worker, wait for it.
+    pub fn get(&mut self, path: &Path) -> DecodeResult {
+        self.collect();
+        loop {
+            match self.cache.get(path) {
+                Some(Entry::Done(result)) => return result.clone(),
+                Some(Entry::Pending) => {
+                    let dequeued = {
+                        let mut queue = self.shared.queue.lock().unwrap();
+                        let index = queue.paths.iter().position(|p| p == path);
+                        index.and_then(|i| queue.paths.remove(i)).is_some()
+                    };
+                    if dequeued {
+                        // not started yet, don't wait for a worker
+                        self.cache.remove(path);
+                    } else if let Ok((path, result)) = self.results.recv() {
+                        self.store(path, result);
+                    } else {
+                        // no more workers, should not happen
+                        self.cache.remove(path);
+                    }
+                },
+                None => {
+                    let result = decode(path);
+                    self.cache.insert(path.to_owned(), Entry::Done(result.clone()));
+                    return result;
+                },
+            }
+        }
+    }
+
+    /// Request decoding of files around given index, evict far away files
+    pub fn update(&mut self, files: &[PathBuf], index: usize) {
+        self.collect();
+
+        {
+            let mut queue = self.shared.queue.lock().unwrap();
+            // requests not started yet are obsolete
+            for path in queue.paths.drain(..) {
+                self.cache.remove(&path);
+            }
+            for i in neighbour_indexes(files.len(), index, self.count) {
+                let path = &files[i];
+                if !self.cache.contains_key(path) {
+                    self.cache.insert(path.clone(), Entry::Pending);
+                    queue.paths.push_back(path.clone());
+                }
+            }
+        }
+        self.shared.condvar.notify_all();
+
+        self.evict(files, index);
+    }
+
+    /// Remove a decoded image, or its pending request
+    pub fn invalidate(&mut self, path: &Path) {
+        self.shared.queue.lock().unwrap().paths.retain(|p| p != path);
+        self.cache.remove(path);
+    }
+
+    /// Remove all decoded images and pending requests
+    pub fn clear(&mut self) {
+        self.shared.queue.lock().unwrap().paths.clear();
+        self.cache.clear();
+    }
+
+    /// Store results sent by workers
+    fn collect(&mut self) {
+        while let Ok((path, result)) = self.results.try_recv() {
+            self.store(path, result);
+        }
+    }
+
+    /// Store a result, ignore it if it is not expected anymore
+    fn store(&mut self, path: PathBuf, result: DecodeResult) {
+        if let Some(entry) = self.cache.get_mut(&path) && matches!(entry, Entry::Pending) {
+            *entry = Entry::Done(result);
+        }
+    }
+
+    /// Evict decoded images until the cache fits in the memory budget
+    fn evict(&mut self, files: &[PathBuf], index: usize) {
+        let mut used: usize = self.cache.values().map(Entry::memory_size).sum();
+        if used <= self.budget {
+            return;
+        }
+
+        let nfiles = files.len();
+        let mut candidates: Vec<(usize, &PathBuf)> = self.cache.iter()
+            .filter(|(_, entry)| entry.memory_size() > 0)
+            .map(|(path, _)| {
+                let distance = files.iter().position(|f| f == path)
+                    .map_or(usize::MAX, |i| {
+                        let d = i.abs_diff(index);
+                        d.min(nfiles - d)
+                    });
+                (distance, path)
+            })
+            .collect();
+        // farthest last
+        candidates.sort_unstable_by_key(|(distance, _)| *distance);
+
+        let mut evicted = Vec::new();
+        while used > self.budget && let Some((_, path)) = candidates.pop() {
+            used -= self.cache[path].memory_size();
+            evicted.push(path.clone());
+        }
+        for path in evicted {
+            self.cache.remove(&path);
+        }
+    }
+}
+
+impl Drop for Prefetcher {
+    fn drop(&mut self) {
+        // Don't wait for workers, a decode could take a while
+        self.shared.queue.lock().unwrap().quit = true;
+        self.shared.condvar.notify_all();
+    }
+}
+
+impl Entry {
+    fn memory_size(&self) -> usize {
+        match self {
+            Self::Done(Ok(image)) => image.memory_size(),
+            _ => 0,
+        }
+    }
+}
+
+
+/// Decode an image, prepare it to be displayed
+fn decode(path: &Path) -> DecodeResult {
+    let mut image = DecodedImage::load(path)?;
+    image.build_levels();
+    Ok(Arc::new(image))
+}
+
+/// Return indexes around `index`, nearest first, wrapping around
+fn neighbour_indexes(len: usize, index: usize, count: usize) -> Vec<usize> {
+    let mut indexes = Vec::new();
+    if len == 0 {
+        return indexes;
+    }
+    indexes.push(index % len);
+    for d in 1..=count.min(len / 2) {
+        indexes.push((index + d) % len);
+        indexes.push((index + len - d) % len);
+    }
+    // with an even length, the opposite index is reached from both sides
+    indexes.dedup();
+    indexes
+}
@@ -0,0 +1,334 @@
+use std::fs::{self, File};
+use std::io::{BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use sdl2::rwops::RWops;
+use sdl2::surface::Surface;
+use crate::decode::DecodedImage;
+use crate::exif;
+use crate::view::View;
+
+
+/// Extension of temporary files, replacing saved files once written
+const TMP_EXTENSION: &str = "riew-tmp";
+
+/// Way to save a transformed image, depending on its format
+enum SaveMethod {
+    /// Update the EXIF orientation (lossless)
+    Exif,
+    Png,
+    Bmp,
+    Tga,
+}
+
+/// PNG chunks kept when re-encoding: colorimetry, and chunks safe to copy (e.g. text)
+///
+/// Other ancillary chunks depend on the encoding of pixels (e.g. `tRNS`, `sBIT`).
+const PNG_KEPT_CHUNKS: [&[u8; 4]; 7] = [b"gAMA", b"cHRM", b"sRGB", b"iCCP", b"cICP", b"mDCV", b"cLLI"];
+
+/// Return how an image would be saved, for confirmation, or why it cannot be
+///
+/// EXIF orientation is ignored when `exif_rotate` is false: it cannot be used to save the orientation.
+pub fn save_description(path: &Path, exif_rotate: bool) -> Result<&'static str, String> {
+    match save_method(path)? {
+        SaveMethod::Exif if !exif_rotate => Err("cannot save the orientation in EXIF data, EXIF rotation is disabled".to_string()),
+        SaveMethod::Exif => Ok("Its EXIF orientation will be updated, pixels are unchanged."),
+        _ => Ok("It will be re-encoded, with 8 bits per channel."),
+    }
+}
+
+/// Save an image with given orientation applied, keep a backup of the original file
+///
+/// JPEG, TIFF and WebP files get their EXIF orientation updated, unless `exif_rotate` is false.
+/// PNG, BMP and TGA files are re-encoded.
+pub fn save_oriented(path: &Path, angle: i32, flip: (bool, bool), exif_rotate: bool) -> Result<(), String> {
+    save_description(path, exif_rotate)?;
+    let method = save_method(path)?;
+    let orientation = exif::orientation_value(angle, flip);
+    let current = match method {
+        SaveMethod::Exif => exif::read_orientation(path).unwrap_or(1),
+        _ => 1,
+    };
+    if orientation == current {
+        return Ok(());  // nothing to change
+    }
+
+    match method {
+        SaveMethod::Exif => {
+            let data = fs::read(path).map_err(|e| e.to_string())?;
+            let data = exif::set_orientation(&data, orientation)?;
+            replace_file(path, |tmp_path| fs::write(tmp_path, &data).map_err(|e| e.to_string()))
+        },
+        _ => {
+            let decoded = DecodedImage::load(path)?;
+            let view = View {
+                image_size: size_as!(decoded.size(), f32),
+                output_size: size_as!(decoded.size(), f32),
+                pos: (0., 0.),
+                zoom: 1.,
+                angle,
+                flip,
+            };
+            let (width, height, mut pixels) = orient_pixels(&decoded, &view);
+            replace_file(path, |tmp_path| match method {
+                SaveMethod::Tga => write_tga(tmp_path, width, height, &pixels),
+                SaveMethod::Png => {
+                    let data = fs::read(path).map_err(|e| e.to_string())?;
+                    let chunks = png_metadata_chunks(&data, view.is_transposed());
+                    let mut file = BufWriter::new(File::create(tmp_path).map_err(|e| e.to_string())?);
+                    write_png_with_chunks(&mut file, width, height, &pixels, &chunks)?;
+                    file.flush().map_err(|e| e.to_string())
+                },
+                _ => {
+                    let pitch = width * 4;
+                    let surface = Surface::from_data(&mut pixels, width, height, pitch, DecodedImage::FORMAT)?;
+                    let mut rwops = RWops::from_file(tmp_path, "wb")?;
+                    surface.save_bmp_rw(&mut rwops)
+                },
+            })
+        },
+    }
+}
+
+/// Replace a file with a temporary one, keep a backup of the original file
+///
+/// The backup is only created once the new content is written.
+/// Permissions of the original file are kept.
+fn replace_file(path: &Path, write: impl FnOnce(&Path) -> Result<(), String>) -> Result<(), String> {
+    let mut tmp_path = path.as_os_str().to_owned();
+    tmp_path.push(".");
+    tmp_path.push(TMP_EXTENSION);
+    let tmp_path = PathBuf::from(tmp_path);
+    let backup = backup_path(path);
+    write(&tmp_path)
+        .and_then(|_| {
+            let permissions = fs::metadata(path).map_err(|e| e.to_string())?.permissions();
+            fs::set_permissions(&tmp_path, permissions).map_err(|e| e.to_string())
+        })
+        .and_then(|_| {
+            if backup.exists() {
+                return Ok(());  // keep the oldest backup, it is the original file
+            }
+            fs::copy(path, &backup).map(|_| ()).map_err(|e| format!("cannot create backup: {e}"))
+        })
+        .and_then(|_| fs::rename(&tmp_path, path).map_err(|e| e.to_string()))
+        .inspect_err(|_| { let _ = fs::remove_file(&tmp_path); })
+}
+
+/// Return the path of the backup of a file
+pub fn backup_path(path: &Path) -> PathBuf {
+    let mut backup = path.as_os_str().to_owned();
+    backup.push("~");
+    PathBuf::from(backup)
+}
+
+/// Check if a file was written when saving another one: its backup, or a temporary file
+///
+/// Other files ending with `~` are not backups, e.g. from editors.
+pub fn is_save_artifact(path: &Path) -> bool {
+    if path.extension().is_some_and(|ext| ext == TMP_EXTENSION) {
+        return true;
+    }
+    let path = path.to_string_lossy();
+    path.strip_suffix('~').is_some_and(|original| Path::new(original).is_file())
+}
+
+/// Guess how to save a file, from its content
+fn save_method(path: &Path) -> Result<SaveMethod, String> {
+    let mut magic = [0u8; 12];
+    let mut file = File::open(path).map_err(|e| e.to_string())?;
+    let n = file.read(&mut magic).map_err(|e| e.to_string())?;
+    let magic = &magic[..n];
+
+    let is_jpeg = magic.starts_with(&[0xFF, 0xD8]);
+    let is_tiff = magic.starts_with(b"II*\0") || magic.starts_with(b"MM\0*");
+    let is_webp = magic.starts_with(b"RIFF") && magic.get(8..12) == Some(b"WEBP");
+    let is_tga = path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("tga"));
+    if is_jpeg || is_tiff || is_webp {
+        Ok(SaveMethod::Exif)
+    } else if magic.starts_with(b"\x89PNG") {
+        Ok(SaveMethod::Png)
+    } else if magic.starts_with(b"BM") {
+        Ok(SaveMethod::Bmp)
+    } else if is_tga {
+        Ok(SaveMethod::Tga)
+    } else {
+        Err("saving is not supported for this format".to_string())
+    }
+}
+
+/// Apply a view orientation to image pixels, return the new size and pixels
+pub fn orient_pixels(decoded: &DecodedImage, view: &View) -> (u32, u32, Vec<u8>) {
+    let pixels = &decoded.frames[0].pixels;
+    let (width, height) = size_as!(view.oriented_size(), u32);
+    let mut result = Vec::with_capacity(pixels.len());
+    for y in 0..height {
+        for x in 0..width {
+            // use pixel centers to avoid rounding issues
+            let (sx, sy) = view.unorient((x as f32 + 0.5, y as f32 + 0.5));
+            let offset = sy as usize * decoded.pitch() + sx as usize * 4;
+            result.extend_from_slice(&pixels[offset..offset + 4]);
+        }
+    }
+    (width, height, result)
+}
+
+/// Return the path of a new file, for a region cropped from a file
+///
+/// The region is in the name, e.g. `photo-crop-10-20-300x200.png`.
+pub fn crop_path(path: &Path, (x, y, width, height): (u32, u32, u32, u32)) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    path.with_file_name(format!("{stem}-crop-{x}-{y}-{width}x{height}.png"))
+}
+
+/// Write `RGBA32` pixels to a new PNG file, fail if it already exists
+pub fn save_png(path: &Path, width: u32, height: u32, pixels: &[u8]) -> Result<(), String> {
+    let file = File::options().write(true).create_new(true).open(path).map_err(|e| format!("{}: {e}", path.display()))?;
+    let mut file = BufWriter::new(file);
+    write_png(&mut file, width, height, pixels)?;
+    file.flush().map_err(|e| e.to_string())
+}
+
+/// Encode `RGBA32` pixels as PNG
+pub fn write_png(writer: impl Write, width: u32, height: u32, pixels: &[u8]) -> Result<(), String> {
+    write_png_with_chunks(writer, width, height, pixels, &[])
+}
+
+/// Encode `RGBA32` pixels as PNG, with additional chunks written before image data
+fn write_png_with_chunks(writer: impl Write, width: u32, height: u32, pixels: &[u8], chunks: &[([u8; 4], Vec<u8>)]) -> Result<(), String> {
+    let mut encoder = png::Encoder::new(writer, width, height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header().map_err(|e| e.to_string())?;
+    for (name, data) in chunks {
+        writer.write_chunk(png::chunk::ChunkType(*name), data).map_err(|e| e.to_string())?;
+    }
+    writer.write_image_data(pixels).map_err(|e| e.to_string())?;
+    writer.finish().map_err(|e| e.to_string())
+}
+
+/// Return the metadata chunks of PNG data still valid once re-encoded, with their data
+///
+/// Pixel dimensions (`pHYs`) are swapped if the image is `transposed`.
+fn png_metadata_chunks(data: &[u8], transposed: bool) -> Vec<([u8; 4], Vec<u8>)> {
+    let mut chunks = Vec::new();
+    let mut pos = 8;  // signature
+    while let Some(header) = data.get(pos..pos + 8) {
+        let length = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as usize;
+        let name: [u8; 4] = header[4..8].try_into().unwrap();
+        let Some(body) = data.get(pos + 8..pos + 8 + length) else { break };
+        // ancillary chunks have a lowercase first letter, safe-to-copy ones a lowercase last letter
+        let ancillary = name[0].is_ascii_lowercase();
+        let safe_to_copy = name[3].is_ascii_lowercase();
+        if &name == b"pHYs" && body.len() == 9 {
+            let mut body = body.to_vec();
+            if transposed {
+                body[..8].rotate_left(4);  // swap pixels per unit along X and Y
+            }
+            chunks.push((name, body));
+        } else if ancillary && (safe_to_copy || PNG_KEPT_CHUNKS.contains(&&name)) {
+            chunks.push((name, body.to_vec()));
+        }
+        pos += 12 + length;  // length, name, data, CRC
+    }
+    chunks
+}
+
+/// Write an uncompressed 32-bit TGA file
+fn write_tga(path: &Path, width: u32, height: u32, pixels: &[u8]) -> Result<(), String> {
+    let width = u16::try_from(width).map_err(|_| "image too large for TGA")?;
+    let height = u16::try_from(height).map_err(|_| "image too large for TGA")?;
+    let mut header = [0u8; 18];
+    header[2] = 2;  // uncompressed true-color
+    header[12..14].copy_from_slice(&width.to_le_bytes());
+    header[14..16].copy_from_slice(&height.to_le_bytes());
+    header[16] = 32;
+    header[17] = 0x28;  // 8 alpha bits, top-left origin
+
+    let mut file = BufWriter::new(File::create(path).map_err(|e| e.to_string())?);
+    file.write_all(&header).map_err(|e| e.to_string())?;
+    for rgba in pixels.chunks_exact(4) {
+        file.write_all(&[rgba[2], rgba[1], rgba[0], rgba[3]]).map_err(|e| e.to_string())?;
+    }
+    file.flush().map_err(|e| e.to_string())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn save_artifacts() {
+        let dir = std::env::temp_dir().join(format!("riew-test-{}-save-artifacts", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("saved.jpg"), b"").unwrap();
+
+        assert!(is_save_artifact(&dir.join("saved.jpg~")));
+        assert!(is_save_artifact(&dir.join("saved.jpg.riew-tmp")));
+        assert!(is_save_artifact(&dir.join("other.jpg.riew-tmp")));
+        // not backups of an existing file
+        assert!(!is_save_artifact(&dir.join("saved.jpg")));
+        assert!(!is_save_artifact(&dir.join("photo.JPG~")));
+        assert!(!is_save_artifact(&dir.join("saved.jpg.tmp")));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn png_chunks() {
+        let mut data = b"\x89PNG\r\n\x1a\n".to_vec();
+        let mut chunk = |name: &[u8; 4], body: &[u8]| {
+            data.extend_from_slice(&(body.len() as u32).to_be_bytes());
+            data.extend_from_slice(name);
+            data.extend_from_slice(body);
+            data.extend_from_slice(&[0; 4]);  // CRC, not checked
+        };
+        chunk(b"IHDR", &[0; 13]);
+        chunk(b"gAMA", &[0, 0, 0xB1, 0x8F]);
+        chunk(b"sBIT", &[5, 6, 5]);
+        chunk(b"pHYs", &[0, 0, 0, 1, 0, 0, 0, 2, 1]);
+        chunk(b"PLTE", &[0; 3]);
+        chunk(b"tRNS", &[0]);
+        chunk(b"IDAT", &[0; 4]);
+        chunk(b"tEXt", b"Title\0Photo");
+        chunk(b"IEND", &[]);
+
+        let names = |chunks: &[([u8; 4], Vec<u8>)]| chunks.iter().map(|(name, _)| *name).collect::<Vec<_>>();
+        let chunks = png_metadata_chunks(&data, false);
+        assert_eq!(names(&chunks), [*b"gAMA", *b"pHYs", *b"tEXt"]);
+        assert_eq!(chunks[1].1, [0, 0, 0, 1, 0, 0, 0, 2, 1]);
+        assert_eq!(chunks[2].1, b"Title\0Photo");
+        let chunks = png_metadata_chunks(&data, true);
+        assert_eq!(chunks[1].1, [0, 0, 0, 2, 0, 0, 0, 1, 1]);
+
+        // truncated data
+        assert_eq!(names(&png_metadata_chunks(&data[..45], false)), [*b"gAMA"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn replace_keeps_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = std::env::temp_dir().join(format!("riew-test-{}-replace", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("image.png");
+        fs::write(&path, b"old").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o640)).unwrap();
+
+        replace_file(&path, |tmp_path| fs::write(tmp_path, b"new").map_err(|e| e.to_string())).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"new");
+        assert_eq!(fs::read(backup_path(&path)).unwrap(), b"old");
+        assert_eq!(fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o640);
+
+        // failed write: no backup update, no temporary file left
+        let result = replace_file(&path, |tmp_path| {
+            fs::write(tmp_path, b"partial").unwrap();
+            Err("failed".to_string())
+        });
+        assert!(result.is_err());
+        assert_eq!(fs::read(&path).unwrap(), b"new");
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 2);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,207 @@
+use std::cmp::Ordering;
+use std::collections::hash_map::{DefaultHasher, RandomState};
+use std::fs;
+use std::hash::{BuildHasher, Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+use clap::ValueEnum;
+use serde::Deserialize;
+
+
+/// Property used to sort files
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Deserialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum SortKey {
+    /// Path, with numbers compared by value
+    #[default]
+    Name,
+    /// Modification time, oldest first
+    Modified,
+    /// File size, smallest first
+    Size,
+    /// Image dimensions (pixel count), smallest first
+    Dimensions,
+    /// File extension
+    Extension,
+    /// Random shuffle
+    Random,
+}
+
+/// Order of the file list
+#[derive(Clone, Copy, Debug)]
+pub struct SortOrder {
+    pub key: SortKey,
+    pub reverse: bool,
+    /// Seed of the random order, kept so that refreshing the list does not reshuffle it
+    pub seed: u64,
+}
+
+/// Value compared to sort files, before names
+#[derive(PartialEq, Eq, PartialOrd, Ord)]
+enum SortValue {
+    Number(u64),
+    Text(String),
+}
+
+
+impl SortKey {
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Name => "name",
+            Self::Modified => "modified",
+            Self::Size => "size",
+            Self::Dimensions => "dimensions",
+            Self::Extension => "extension",
+            Self::Random => "random",
+        }
+    }
+
+    /// Return the next sort key, wrapping around
+    pub fn next(self) -> Self {
+        let keys = Self::value_variants();
+        let index = keys.iter().position(|k| *k == self).unwrap_or(0);
+        keys[(index + 1) % keys.len()]
+    }
+}
+
+impl SortOrder {
+    pub fn new(key: SortKey, reverse: bool) -> Self {
+        Self { key, reverse, seed: random_seed() }
+    }
+
+    /// Sort files, ties are sorted by name
+    pub fn sort(&self, files: &mut Vec<PathBuf>) {
+        let mut keyed: Vec<(SortValue, PathBuf)> = files.drain(..)
+            .map(|path| (self.value(&path), path))
+            .collect();
+        keyed.sort_by(|(va, pa), (vb, pb)| va.cmp(vb).then_with(|| natural_cmp(pa, pb)));
+        if self.reverse {
+            keyed.reverse();
+        }
+        files.extend(keyed.into_iter().map(|(_, path)| path));
+    }
+
+    /// Return the value used to sort a file
+    fn value(&self, path: &Path) -> SortValue {
+        match self.key {
+            SortKey::Name => SortValue::Number(0),
+            SortKey::Modified => {
+                let mtime = fs::metadata(path).and_then(|m| m.modified()).ok()
+                    .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+                    .map_or(0, |d| d.as_nanos() as u64);
+                SortValue::Number(mtime)
+            },
+            SortKey::Size => SortValue::Number(fs::metadata(path).map_or(0, |m| m.len())),
+            SortKey::Dimensions => {
+                // only read the header, decoding each image would be way too slow
+                let pixels = imagesize::size(path).map_or(0, |s| s.width as u64 * s.height as u64);
+                SortValue::Number(pixels)
+            },
+            SortKey::Extension => {
+                let ext = path.extension().map_or(String::new(), |e| e.to_string_lossy().to_lowercase());
+                SortValue::Text(ext)
+            },
+            SortKey::Random => {
+                let mut hasher = DefaultHasher::new();
+                self.seed.hash(&mut hasher);
+                path.hash(&mut hasher);
+                SortValue::Number(hasher.finish())
+            },
+        }
+    }
+}
+
+
+/// Return a new random seed
+fn random_seed() -> u64 {
+    RandomState::new().hash_one(0)
+}
+
+/// Compare paths, with sequences of digits compared by numeric value
+///
+/// Comparison is case-insensitive, ties are broken by a byte-wise comparison.
+fn natural_cmp(a: &Path, b: &Path) -> Ordering {
+    let (a, b) = (a.to_string_lossy(), b.to_string_lossy());
+    let (mut ia, mut ib) = (a.chars().peekable(), b.chars().peekable());
+    loop {
+        match (ia.peek().copied(), ib.peek().copied()) {
+            (None, None) => return a.cmp(&b),
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(ca), Some(cb)) if ca.is_ascii_digit() && cb.is_ascii_digit() => {
+                let mut na = String::new();
+                while let Some(c) = ia.next_if(char::is_ascii_digit) {
+                    na.push(c);
+                }
+                let mut nb = String::new();
+                while let Some(c) = ib.next_if(char::is_ascii_digit) {
+                    nb.push(c);
+                }
+                let (na, nb) = (na.trim_start_matches('0'), nb.trim_start_matches('0'));
+                let ordering = na.len().cmp(&nb.len()).then_with(|| na.cmp(nb));
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+            },
+            (Some(ca), Some(cb)) => {
+                let ordering = ca.to_lowercase().cmp(cb.to_lowercase());
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+                ia.next();
+                ib.next();
+            },
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cmp(a: &str, b: &str) -> Ordering {
+        natural_cmp(Path::new(a), Path::new(b))
+    }
+
+    #[test]
+    fn numbers() {
+        assert_eq!(cmp("img2.png", "img10.png"), Ordering::Less);
+        assert_eq!(cmp("img10.png", "img2.png"), Ordering::Greater);
+        assert_eq!(cmp("img2.png", "img2.png"), Ordering::Equal);
+        assert_eq!(cmp("img2a.png", "img2b.png"), Ordering::Less);
+        assert_eq!(cmp("a/img9.png", "a10/img1.png"), Ordering::Less);
+        // digits come before letters
+        assert_eq!(cmp("img1.png", "imga.png"), Ordering::Less);
+    }
+
+    #[test]
+    fn leading_zeros() {
+        assert_eq!(cmp("img002.png", "img10.png"), Ordering::Less);
+        assert_eq!(cmp("img010.png", "img9.png"), Ordering::Greater);
+        // same value: byte-wise tie break
+        assert_eq!(cmp("img02.png", "img2.png"), Ordering::Less);
+        assert_eq!(cmp("img0.png", "img00.png"), Ordering::Less);
+        assert_eq!(cmp("img00.png", "img0.png"), Ordering::Greater);
+    }
+
+    #[test]
+    fn long_numbers() {
+        // longer than u64
+        let a = "img18446744073709551616000.png";
+        let b = "img18446744073709551616001.png";
+        assert_eq!(cmp(a, b), Ordering::Less);
+        assert_eq!(cmp("img9999999999999999999999.png", a), Ordering::Less);
+        assert_eq!(cmp(a, a), Ordering::Equal);
+    }
+
+    #[test]
+    fn case() {
+        assert_eq!(cmp("Apple.png", "banana.png"), Ordering::Less);
+        assert_eq!(cmp("apple.png", "Banana.png"), Ordering::Less);
+        assert_eq!(cmp("IMG10.png", "img9.png"), Ordering::Greater);
+        // same name ignoring case: byte-wise tie break, uppercase first
+        assert_eq!(cmp("Img.png", "img.png"), Ordering::Less);
+        assert_eq!(cmp("img.png", "IMG.png"), Ordering::Greater);
+    }
+}
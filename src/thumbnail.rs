@@ -0,0 +1,305 @@
+use std::collections::{HashSet, VecDeque};
+use std::fs::{self, File};
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Condvar, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+use std::time::UNIX_EPOCH;
+use serde::Deserialize;
+use sdl2::event::{Event, EventSender};
+use crate::decode::{self, DecodedImage, Frame};
+use crate::exif;
+use crate::save;
+use crate::view::View;
+
+
+type ThumbnailResult = Result<DecodedImage, String>;
+
+/// Thumbnail size, as defined by the freedesktop thumbnail specification
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ThumbnailSize {
+    /// 128 pixels
+    #[default]
+    Normal,
+    /// 256 pixels
+    Large,
+}
+
+/// Create thumbnails in background threads
+///
+/// Thumbnails are cached on disk, following the freedesktop thumbnail specification.
+/// When thumbnails are ready, an SDL user event is pushed to wake up the main loop.
+pub struct Thumbnailer {
+    shared: Arc<Shared>,
+    results: Receiver<(PathBuf, ThumbnailResult)>,
+    /// Requested files, queued or being processed
+    requested: HashSet<PathBuf>,
+    /// SDL event type of wake events
+    pub event_type: u32,
+}
+
+/// Data shared with worker threads
+struct Shared {
+    queue: Mutex<Queue>,
+    condvar: Condvar,
+    /// Set when a wake event has been pushed, but results have not been retrieved yet
+    pending: AtomicBool,
+    sender: EventSender,
+    event_type: u32,
+}
+
+/// Files waiting for their thumbnail, first ones first
+struct Queue {
+    paths: VecDeque<PathBuf>,
+    quit: bool,
+}
+
+
+impl ThumbnailSize {
+    /// Return the maximum width and height of thumbnails, in pixels
+    pub const fn pixels(self) -> u32 {
+        match self {
+            Self::Normal => 128,
+            Self::Large => 256,
+        }
+    }
+
+    /// Return the name of the cache subdirectory
+    const fn dir_name(self) -> &'static str {
+        match self {
+            Self::Normal => "normal",
+            Self::Large => "large",
+        }
+    }
+}
+
+impl Thumbnailer {
+    const WORKERS: usize = 2;
+
+    /// Create the thumbnailer and start its worker threads
+    pub fn new(size: ThumbnailSize, sender: EventSender, event_type: u32) -> Self {
+        let shared = Arc::new(Shared {
+            queue: Mutex::new(Queue { paths: VecDeque::new(), quit: false }),
+            condvar: Condvar::new(),
+            pending: AtomicBool::new(false),
+            sender,
+            event_type,
+        });
+        let (tx, results) = mpsc::channel();
+        for _ in 0..Self::WORKERS {
+            let shared = shared.clone();
+            let tx = tx.clone();
+            thread::spawn(move || Self::worker(&shared, &tx, size));
+        }
+        Self { shared, results, requested: HashSet::new(), event_type }
+    }
+
+    /// Worker thread: create thumbnails of queued files until asked to quit
+    fn worker(shared: &Shared, tx: &Sender<(PathBuf, ThumbnailResult)>, size: ThumbnailSize) {
+        loop {
+            let path = {
+                let mut queue = shared.queue.lock().unwrap();
+                loop {
+                    if queue.quit {
+                        return;
+                    }
+                    if let Some(path) = queue.paths.pop_front() {
+                        break path;
+                    }
+                    queue = shared.condvar.wait(queue).unwrap();
+                }
+            };
+            let result = load_thumbnail(&path, size);
+            if tx.send((path, result)).is_err() {
+                return;  // thumbnailer dropped
+            }
+            if !shared.pending.swap(true, Ordering::AcqRel) {
+                let _ = shared.sender.push_event(Event::User {
+                    timestamp: 0,
+                    window_id: 0,
+                    type_: shared.event_type,
+                    code: 0,
+                    data1: std::ptr::null_mut(),
+                    data2: std::ptr::null_mut(),
+                });
+            }
+        }
+    }
+
+    /// Request thumbnails, in priority order
+    ///
+    /// Previous requests not started yet are dropped.
+    pub fn request(&mut self, paths: &[PathBuf]) {
+        {
+            let mut queue = self.shared.queue.lock().unwrap();
+            for path in queue.paths.drain(..) {
+                self.requested.remove(&path);
+            }
+            for path in paths {
+                if self.requested.insert(path.clone()) {
+                    queue.paths.push_back(path.clone());
+                }
+            }
+        }
+        self.shared.condvar.notify_all();
+    }
+
+    /// Retrieve created thumbnails
+    pub fn results(&mut self) -> Vec<(PathBuf, ThumbnailResult)> {
+        self.shared.pending.store(false, Ordering::Release);
+        let results: Vec<_> = self.results.try_iter().collect();
+        for (path, _) in &results {
+            self.requested.remove(path);
+        }
+        results
+    }
+}
+
+impl Drop for Thumbnailer {
+    fn drop(&mut self) {
+        self.shared.queue.lock().unwrap().quit = true;
+        self.shared.condvar.notify_all();
+    }
+}
+
+
+/// Load a thumbnail from the cache, or create it
+fn load_thumbnail(path: &Path, size: ThumbnailSize) -> ThumbnailResult {
+    let uri = file_uri(path)?;
+    let mtime = fs::metadata(path)
+        .and_then(|m| m.modified())
+        .map_err(|e| e.to_string())?
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    let cache_path = cache_path(&uri, size);
+
+    if let Some(cache_path) = &cache_path
+        && is_cache_valid(cache_path, &uri, mtime)
+        && let Ok(thumbnail) = DecodedImage::load(cache_path) {
+        return Ok(thumbnail);
+    }
+
+    let thumbnail = create_thumbnail(path, size)?;
+    if let Some(cache_path) = &cache_path
+        && let Err(e) = save_thumbnail(cache_path, &thumbnail, &uri, mtime) {
+        eprintln!("cannot save thumbnail: {}: {e}", cache_path.display());
+    }
+    Ok(thumbnail)
+}
+
+/// Decode an image, create its thumbnail
+///
+/// The thumbnail is oriented according to EXIF data, as expected by other applications.
+fn create_thumbnail(path: &Path, size: ThumbnailSize) -> ThumbnailResult {
+    let image = DecodedImage::load(path)?;
+    let max_size = size.pixels() as f32;
+    let scale = 1f32.min(max_size / image.width as f32).min(max_size / image.height as f32);
+    let (width, height) = ((image.width as f32 * scale).round().max(1.) as u32, (image.height as f32 * scale).round().max(1.) as u32);
+    let pixels = decode::downscale_pixels(image.size(), &image.frames[0].pixels, (width, height));
+    let mut thumbnail = DecodedImage {
+        width,
+        height,
+        frames: vec![Frame { pixels, delay: 0 }],
+        orientation: None,
+        levels: Vec::new(),
+        channel_bits: DecodedImage::FULL_DEPTH,
+    };
+
+    if let Some(orientation) = image.orientation {
+        let (angle, flip_h) = exif::orientation_transform(orientation);
+        let view = View {
+            image_size: (width as f32, height as f32),
+            output_size: (width as f32, height as f32),
+            pos: (0., 0.),
+            zoom: 1.,
+            angle,
+            flip: (flip_h, false),
+        };
+        let (width, height, pixels) = save::orient_pixels(&thumbnail, &view);
+        thumbnail.width = width;
+        thumbnail.height = height;
+        thumbnail.frames[0].pixels = pixels;
+    }
+    Ok(thumbnail)
+}
+
+/// Check if a cached thumbnail exists and is up to date
+fn is_cache_valid(cache_path: &Path, uri: &str, mtime: u64) -> bool {
+    let Ok(file) = File::open(cache_path) else { return false };
+    let Ok(reader) = png::Decoder::new(BufReader::new(file)).read_info() else { return false };
+    let texts = &reader.info().uncompressed_latin1_text;
+    let text = |key: &str| texts.iter().find(|t| t.keyword == key).map(|t| t.text.as_str());
+    text("Thumb::URI") == Some(uri) && text("Thumb::MTime") == Some(mtime.to_string().as_str())
+}
+
+/// Save a thumbnail to the cache, with required metadata
+fn save_thumbnail(cache_path: &Path, thumbnail: &DecodedImage, uri: &str, mtime: u64) -> Result<(), String> {
+    let dir = cache_path.parent().ok_or("invalid cache path")?;
+    let mut builder = fs::DirBuilder::new();
+    builder.recursive(true);
+    #[cfg(unix)]
+    {
+        // the specification requires the cache to be private
+        use std::os::unix::fs::DirBuilderExt;
+        builder.mode(0o700);
+    }
+    builder.create(dir).map_err(|e| e.to_string())?;
+
+    // write to a temporary file, then rename it, so that other applications never read a partial file
+    let mut tmp_path = cache_path.as_os_str().to_owned();
+    tmp_path.push(format!(".riew-{}.tmp", std::process::id()));
+    let tmp_path = PathBuf::from(tmp_path);
+    let result = (|| -> Result<(), String> {
+        let file = File::create(&tmp_path).map_err(|e| e.to_string())?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            file.set_permissions(fs::Permissions::from_mode(0o600)).map_err(|e| e.to_string())?;
+        }
+        let mut encoder = png::Encoder::new(file, thumbnail.width, thumbnail.height);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        let texts = [
+            ("Thumb::URI", uri.to_string()),
+            ("Thumb::MTime", mtime.to_string()),
+            ("Software", "riew".to_string()),
+        ];
+        for (key, text) in texts {
+            encoder.add_text_chunk(key.to_string(), text).map_err(|e| e.to_string())?;
+        }
+        let mut writer = encoder.write_header().map_err(|e| e.to_string())?;
+        writer.write_image_data(&thumbnail.frames[0].pixels).map_err(|e| e.to_string())?;
+        writer.finish().map_err(|e| e.to_string())?;
+        fs::rename(&tmp_path, cache_path).map_err(|e| e.to_string())
+    })();
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp_path);
+    }
+    result
+}
+
+/// Return the path of a cached thumbnail, None if there is no cache directory
+fn cache_path(uri: &str, size: ThumbnailSize) -> Option<PathBuf> {
+    let name = format!("{:x}.png", md5::compute(uri.as_bytes()));
+    dirs::cache_dir().map(|dir| dir.join("thumbnails").join(size.dir_name()).join(name))
+}
+
+/// Return the URI of a file, as used to identify thumbnails
+///
+/// Characters are escaped like GLib does, so that other applications share the same thumbnails.
+fn file_uri(path: &Path) -> Result<String, String> {
+    const UNESCAPED: &[u8] = b"!$&'()*+,-./:=@_~";
+    let path = path.canonicalize().map_err(|e| e.to_string())?;
+    let mut uri = String::from("file://");
+    for &b in path.as_os_str().as_encoded_bytes() {
+        if b.is_ascii_alphanumeric() || UNESCAPED.contains(&b) {
+            uri.push(b as char);
+        } else {
+            uri += &format!("%{b:02X}");
+        }
+    }
+    Ok(uri)
+}
@@ -0,0 +1,331 @@
+/// Placement of an image on the screen
+///
+/// Image coordinates are pixels of the image, as stored in the file.
+/// The image is flipped, then rotated around its center, giving "oriented" coordinates.
+/// Oriented coordinates are then scaled so that `pos` is displayed at the center of the output.
+#[derive(Clone, Copy, Debug)]
+pub struct View {
+    /// Image size, in pixels
+    pub image_size: (f32, f32),
+    /// Output size, in pixels
+    pub output_size: (f32, f32),
+    /// Image position displayed at the center of the output
+    pub pos: (f32, f32),
+    pub zoom: f32,
+    /// Clockwise rotation angle, in degrees, multiple of 90
+    pub angle: i32,
+    /// Horizontal and vertical flips, applied before the rotation
+    pub flip: (bool, bool),
+}
+
+impl View {
+    /// Return the rotation angle, normalized to `[0, 360)`
+    pub const fn normalized_angle(&self) -> i32 {
+        self.angle.rem_euclid(360)
+    }
+
+    /// Return true if width and height are swapped by the rotation
+    pub const fn is_transposed(&self) -> bool {
+        self.normalized_angle() % 180 != 0
+    }
+
+    /// Return the size of the image, once rotated
+    pub const fn oriented_size(&self) -> (f32, f32) {
+        let (sx, sy) = self.image_size;
+        if self.is_transposed() { (sy, sx) } else { (sx, sy) }
+    }
+
+    /// Convert image coordinates to oriented coordinates
+    pub fn orient(&self, pos: (f32, f32)) -> (f32, f32) {
+        let (sx, sy) = self.image_size;
+        let (mut x, mut y) = (pos.0 - sx / 2., pos.1 - sy / 2.);
+        if self.flip.0 {
+            x = -x;
+        }
+        if self.flip.1 {
+            y = -y;
+        }
+        let (x, y) = rotate((x, y), self.normalized_angle());
+        let (osx, osy) = self.oriented_size();
+        (x + osx / 2., y + osy / 2.)
+    }
+
+    /// Convert oriented coordinates to image coordinates
+    pub fn unorient(&self, pos: (f32, f32)) -> (f32, f32) {
+        let (osx, osy) = self.oriented_size();
+        let (x, y) = (pos.0 - osx / 2., pos.1 - osy / 2.);
+        let (mut x, mut y) = rotate((x, y), 360 - self.normalized_angle());
+        if self.flip.0 {
+            x = -x;
+        }
+        if self.flip.1 {
+            y = -y;
+        }
+        let (sx, sy) = self.image_size;
+        (x + sx / 2., y + sy / 2.)
+    }
+
+    /// Convert image coordinates to screen coordinates
+    pub fn image_to_screen(&self, pos: (f32, f32)) -> (f32, f32) {
+        let (x, y) = self.orient(pos);
+        let (cx, cy) = self.orient(self.pos);
+        let (out_sx, out_sy) = self.output_size;
+        (out_sx / 2. + (x - cx) * self.zoom, out_sy / 2. + (y - cy) * self.zoom)
+    }
+
+    /// Convert screen coordinates to image coordinates
+    ///
+    /// Returned position may be outside the image.
+    pub fn screen_to_image(&self, pos: (f32, f32)) -> (f32, f32) {
+        let (cx, cy) = self.orient(self.pos);
+        let (out_sx, out_sy) = self.output_size;
+        let x = cx + (pos.0 - out_sx / 2.) / self.zoom;
+        let y = cy + (pos.1 - out_sy / 2.) / self.zoom;
+        self.unorient((x, y))
+    }
+
+    /// Return true if image coordinates are inside the image
+    pub fn contains(&self, pos: (f32, f32)) -> bool {
+        let (sx, sy) = self.image_size;
+        (0. ..=sx).contains(&pos.0) && (0. ..=sy).contains(&pos.1)
+    }
+
+    /// Return the destination rectangle to draw the image, before rotation
+    ///
+    /// The rotation is done around the center of the rectangle.
+    /// Return `(x, y, width, height)`.
+    pub fn draw_rect(&self) -> (f32, f32, f32, f32) {
+        let (sx, sy) = self.image_size;
+        self.region_draw_rect((0., 0., sx, sy))
+    }
+
+    /// Return the destination rectangle to draw a region of the image, before rotation
+    ///
+    /// The region is given in image coordinates, as `(x, y, width, height)`.
+    /// Like for the whole image, the rotation is done around the center of the rectangle.
+    pub fn region_draw_rect(&self, (x, y, w, h): (f32, f32, f32, f32)) -> (f32, f32, f32, f32) {
+        let (cx, cy) = self.image_to_screen((x + w / 2., y + h / 2.));
+        let (dst_sx, dst_sy) = (w * self.zoom, h * self.zoom);
+        (cx - dst_sx / 2., cy - dst_sy / 2., dst_sx, dst_sy)
+    }
+
+    /// Return true if a region of the image, in image coordinates, is visible in the output
+    pub fn is_region_visible(&self, (x, y, w, h): (f32, f32, f32, f32)) -> bool {
+        // rotations are multiples of 90 degrees: opposite corners give the screen bounds
+        let (ax, ay) = self.image_to_screen((x, y));
+        let (bx, by) = self.image_to_screen((x + w, y + h));
+        let (out_sx, out_sy) = self.output_size;
+        ax.min(bx) < out_sx && ax.max(bx) > 0. && ay.min(by) < out_sy && ay.max(by) > 0.
+    }
+
+    /// Return the position, centered or clamped so that the image covers as much of the output as possible
+    pub fn clamped_pos(&self) -> (f32, f32) {
+        let (img_sx, img_sy) = self.oriented_size();
+        let (out_sx, out_sy) = self.output_size;
+        let (dst_sx, dst_sy) = (out_sx / self.zoom, out_sy / self.zoom);
+
+        let (px, py) = self.orient(self.pos);
+        let px = if img_sx <= dst_sx {
+            img_sx / 2.
+        } else {
+            px.clamp(dst_sx / 2., img_sx - dst_sx / 2.)
+        };
+        let py = if img_sy <= dst_sy {
+            img_sy / 2.
+        } else {
+            py.clamp(dst_sy / 2., img_sy - dst_sy / 2.)
+        };
+        self.unorient((px, py))
+    }
+
+    /// Return the zoom level to display the whole image, without enlarging it
+    pub fn fit_zoom(&self) -> f32 {
+        let (out_sx, out_sy) = self.output_size;
+        let (img_sx, img_sy) = self.oriented_size();
+        1f32.min(out_sx / img_sx).min(out_sy / img_sy)
+    }
+
+    /// Return true if the whole image fits in the output
+    pub fn is_adjusted(&self) -> bool {
+        let (out_sx, out_sy) = self.output_size;
+        let (img_sx, img_sy) = self.oriented_size();
+        // Round because of possible accuracy issues for large images
+        out_sx >= (img_sx * self.zoom).round() && out_sy >= (img_sy * self.zoom).round()
+    }
+
+    /// Return the view of a part of the output, the image staying at the same place on screen
+    ///
+    /// The part is given in screen coordinates, as `(x, y, width, height)`.
+    pub fn sub_view(&self, (x, y, w, h): (f32, f32, f32, f32)) -> Self {
+        let pos = self.screen_to_image((x + w / 2., y + h / 2.));
+        Self { output_size: (w, h), pos, ..*self }
+    }
+
+    /// Return the same view for an image of another size, at the same relative position
+    pub fn with_image_size(&self, size: (f32, f32)) -> Self {
+        let (sx, sy) = self.image_size;
+        let pos = (self.pos.0 * size.0 / sx, self.pos.1 * size.1 / sy);
+        Self { image_size: size, pos, ..*self }
+    }
+}
+
+
+/// Rotate a vector clockwise (with Y axis pointing down), by a multiple of 90 degrees
+fn rotate((x, y): (f32, f32), angle: i32) -> (f32, f32) {
+    match angle.rem_euclid(360) {
+        90 => (-y, x),
+        180 => (-x, -y),
+        270 => (y, -x),
+        _ => (x, y),
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn view(angle: i32, flip: (bool, bool)) -> View {
+        View {
+            image_size: (400., 200.),
+            output_size: (100., 100.),
+            pos: (200., 100.),
+            zoom: 1.,
+            angle,
+            flip,
+        }
+    }
+
+    fn assert_near(a: (f32, f32), b: (f32, f32)) {
+        assert!((a.0 - b.0).abs() < 1e-3 && (a.1 - b.1).abs() < 1e-3, "{a:?} != {b:?}");
+    }
+
+    #[test]
+    fn orient_roundtrip() {
+        for angle in [0, 90, 180, 270, -90] {
+            for flip in [(false, false), (true, false), (false, true), (true, true)] {
+                let v = view(angle, flip);
+                for pos in [(0., 0.), (400., 200.), (10., 150.)] {
+                    assert_near(v.unorient(v.orient(pos)), pos);
+                    assert_near(v.screen_to_image(v.image_to_screen(pos)), pos);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn orient_corners() {
+        // top-left corner goes to top-right when rotated clockwise
+        assert_near(view(90, (false, false)).orient((0., 0.)), (200., 0.));
+        assert_near(view(180, (false, false)).orient((0., 0.)), (400., 200.));
+        assert_near(view(270, (false, false)).orient((0., 0.)), (0., 400.));
+        assert_near(view(-90, (false, false)).orient((0., 0.)), (0., 400.));
+        assert_near(view(0, (true, false)).orient((0., 0.)), (400., 0.));
+        assert_near(view(0, (false, true)).orient((0., 0.)), (0., 200.));
+        // flip is applied before rotation
+        assert_near(view(90, (true, false)).orient((0., 0.)), (200., 400.));
+    }
+
+    #[test]
+    fn screen_to_image() {
+        let v = view(90, (false, false));
+        assert_near(v.screen_to_image((50., 50.)), (200., 100.));
+        // moving right on screen moves up in the image
+        assert_near(v.screen_to_image((60., 50.)), (200., 90.));
+        // moving down on screen moves right in the image
+        assert_near(v.screen_to_image((50., 60.)), (210., 100.));
+
+        let v = View { zoom: 2., ..view(0, (true, false)) };
+        assert_near(v.screen_to_image((60., 50.)), (195., 100.));
+    }
+
+    #[test]
+    fn fit_zoom() {
+        let v = View { output_size: (800., 500.), ..view(0, (false, false)) };
+        assert_eq!(v.fit_zoom(), 1.);
+        let v = View { output_size: (200., 500.), ..view(0, (false, false)) };
+        assert_eq!(v.fit_zoom(), 0.5);
+        // rotated: 200 x 400
+        let v = View { output_size: (200., 500.), ..view(90, (false, false)) };
+        assert_eq!(v.fit_zoom(), 1.);
+        assert!(v.is_adjusted());
+        let v = View { output_size: (800., 100.), ..view(270, (false, false)) };
+        assert_eq!(v.fit_zoom(), 0.25);
+        assert!(!View { zoom: 0.3, ..v }.is_adjusted());
+    }
+
+    #[test]
+    fn clamped_pos() {
+        // output is 100x100: X can move within [50, 350], Y within [50, 150]
+        let v = View { pos: (0., 0.), ..view(0, (false, false)) };
+        assert_near(v.clamped_pos(), (50., 50.));
+        // rotated: clamp is done on oriented axes, but result is in image coordinates
+        let v = View { pos: (0., 0.), ..view(90, (false, false)) };
+        assert_near(v.clamped_pos(), (50., 50.));
+        let v = View { pos: (1000., 1000.), ..view(90, (true, false)) };
+        assert_near(v.clamped_pos(), (350., 150.));
+        // image smaller than output: centered
+        let v = View { pos: (0., 0.), zoom: 0.1, ..view(90, (false, false)) };
+        assert_near(v.clamped_pos(), (200., 100.));
+    }
+
+    #[test]
+    fn draw_rect() {
+        let v = view(90, (false, false));
+        assert_near(v.image_to_screen((200., 100.)), (50., 50.));
+        let (x, y, w, h) = v.draw_rect();
+        assert_near((x, y), (-150., -50.));
+        assert_near((w, h), (400., 200.));
+        // off-center position is rotated
+        let v = View { pos: (100., 100.), ..view(90, (false, false)) };
+        assert_near(v.image_to_screen((200., 100.)), (50., 150.));
+    }
+
+    #[test]
+    fn region_draw_rect() {
+        // regions are placed as if the whole image was drawn, then rotated around their own center
+        let v = view(90, (false, false));
+        let (x, y, w, h) = v.region_draw_rect((0., 0., 200., 200.));
+        assert_near((x + w / 2., y + h / 2.), v.image_to_screen((100., 100.)));
+        assert_near((w, h), (200., 200.));
+        let v = view(0, (true, false));
+        let (x, _, _, _) = v.region_draw_rect((0., 0., 100., 200.));
+        assert_near((x, 0.), (150., 0.));
+    }
+
+    #[test]
+    fn is_region_visible() {
+        // output shows image X within [150, 250], Y within [50, 150]
+        let v = view(0, (false, false));
+        assert!(v.is_region_visible((100., 0., 100., 100.)));
+        assert!(!v.is_region_visible((0., 0., 100., 100.)));
+        // rotated: output shows image X within [150, 250], Y within [50, 150] as well
+        let v = view(90, (false, false));
+        assert!(v.is_region_visible((140., 40., 20., 20.)));
+        assert!(!v.is_region_visible((300., 0., 100., 200.)));
+        let v = view(0, (true, false));
+        assert!(v.is_region_visible((150., 50., 10., 10.)));
+        assert!(!v.is_region_visible((300., 0., 100., 200.)));
+    }
+
+    #[test]
+    fn sub_view() {
+        // pixels stay at the same place on screen, relatively to the part
+        for v in [view(0, (false, false)), view(90, (true, false)), View { zoom: 2., ..view(270, (false, true)) }] {
+            let sub = v.sub_view((60., 0., 40., 100.));
+            assert_eq!(sub.output_size, (40., 100.));
+            for pos in [(60., 0.), (75., 30.), (100., 100.)] {
+                assert_near(sub.screen_to_image((pos.0 - 60., pos.1)), v.screen_to_image(pos));
+            }
+        }
+    }
+
+    #[test]
+    fn with_image_size() {
+        let v = View { pos: (100., 50.), ..view(90, (false, false)) };
+        let other = v.with_image_size((800., 400.));
+        assert_near(other.pos, (200., 100.));
+        assert_eq!(other.image_size, (800., 400.));
+        assert_eq!((other.zoom, other.angle), (v.zoom, v.angle));
+    }
+}
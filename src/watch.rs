@@ -0,0 +1,101 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver};
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher as _};
+use notify::event::ModifyKind;
+use sdl2::event::{Event, EventSender};
+
+
+/// Watch requested paths for changes
+///
+/// Changes are notified to the main loop with an SDL user event, so that it wakes up.
+/// Only one wake event is pushed until changes are retrieved.
+pub struct Watcher {
+    _watcher: RecommendedWatcher,
+    paths: Receiver<PathBuf>,
+    /// Set when a wake event has been pushed, but changes have not been retrieved yet
+    pending: Arc<AtomicBool>,
+    /// True if the current directory is watched as `.`, instead of an empty path
+    cwd_watched: bool,
+    /// SDL event type of wake events
+    pub event_type: u32,
+}
+
+impl Watcher {
+    /// Watch directories (recursively or not) and files
+    pub fn new(paths: &[PathBuf], recursive: bool, sender: EventSender, event_type: u32) -> Result<Self, String> {
+        let (tx, rx) = mpsc::channel();
+        let pending = Arc::new(AtomicBool::new(false));
+        let handler = {
+            let pending = pending.clone();
+            move |result: notify::Result<notify::Event>| {
+                let event = match result {
+                    Ok(event) => event,
+                    Err(e) => return eprintln!("file watch error: {e}"),
+                };
+                // ignore accesses, and metadata changes (e.g. access time)
+                if !matches!(event.kind, EventKind::Create(_) | EventKind::Remove(_) | EventKind::Modify(_))
+                    || matches!(event.kind, EventKind::Modify(ModifyKind::Metadata(_))) {
+                    return;
+                }
+                for path in event.paths {
+                    let _ = tx.send(path);
+                }
+                if !pending.swap(true, Ordering::AcqRel) {
+                    let _ = sender.push_event(Event::User {
+                        timestamp: 0,
+                        window_id: 0,
+                        type_: event_type,
+                        code: 0,
+                        data1: std::ptr::null_mut(),
+                        data2: std::ptr::null_mut(),
+                    });
+                }
+            }
+        };
+
+        let mut watcher = notify::recommended_watcher(handler).map_err(|e| e.to_string())?;
+        let mut cwd_watched = false;
+        for path in paths {
+            let (path, mode) = if path.as_os_str().is_empty() || path.is_dir() {
+                (path.as_path(), Self::dir_mode(recursive))
+            } else if let Some(parent) = path.parent() {
+                // watch the parent directory, to be notified when the file is replaced
+                (parent, RecursiveMode::NonRecursive)
+            } else {
+                continue;
+            };
+            // an empty path is the current directory, but it cannot be watched as is
+            let path = if path.as_os_str().is_empty() {
+                cwd_watched = true;
+                Path::new(".")
+            } else {
+                path
+            };
+            watcher.watch(path, mode).map_err(|e| format!("{}: {e}", path.display()))?;
+        }
+
+        Ok(Self { _watcher: watcher, paths: rx, pending, cwd_watched, event_type })
+    }
+
+    const fn dir_mode(recursive: bool) -> RecursiveMode {
+        if recursive { RecursiveMode::Recursive } else { RecursiveMode::NonRecursive }
+    }
+
+    /// Retrieve changed paths, without duplicates
+    ///
+    /// Paths are returned as they would be listed: files of the current directory have no `./` prefix.
+    pub fn changed_paths(&self) -> Vec<PathBuf> {
+        self.pending.store(false, Ordering::Release);
+        let mut paths: Vec<PathBuf> = self.paths.try_iter()
+            .map(|path| match path.strip_prefix(".") {
+                Ok(relative) if self.cwd_watched => relative.to_owned(),
+                _ => path,
+            })
+            .collect();
+        paths.sort_unstable();
+        paths.dedup();
+        paths
+    }
+}